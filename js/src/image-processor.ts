@@ -34,13 +34,18 @@ export class ImageProcessor {
       imageData.data.byteOffset,
       imageData.data.byteLength
     );
-    const result = this.detector.detect_from_image(
-      pixelData,
-      imageData.width,
-      imageData.height
-    );
-
-    return result as unknown as ChromakeyResult | null;
+    try {
+      const result = this.detector.detect_from_image(
+        pixelData,
+        imageData.width,
+        imageData.height
+      );
+      return result as unknown as ChromakeyResult | null;
+    } catch (e) {
+      // "No key found" is a normal outcome; only rethrow real failures
+      if (isNoKeyError(e)) return null;
+      throw e;
+    }
   }
 
   private toImageData(
@@ -66,3 +71,14 @@ export class ImageProcessor {
     return ctx.getImageData(0, 0, canvas.width, canvas.height);
   }
 }
+
+function isNoKeyError(e: unknown): boolean {
+  if (!(e instanceof Error) || e.name !== 'DetectError') return false;
+  const kind = (e as Error & { kind?: string }).kind;
+  return (
+    kind === 'grayscale' ||
+    kind === 'no_dominant_color' ||
+    kind === 'desaturated' ||
+    kind === 'transparent'
+  );
+}
@@ -9,8 +9,12 @@ export interface ChromakeyResult {
 export interface DetectionConfig {
   minAreaPercentage?: number;
   minSaturation?: number;
+  grayscaleSaturation?: number; // Saturation below which a pixel counts as gray (default: 0.15)
   edgeSamplePercentage?: number;
   confidenceThreshold?: number;
+  colorSpace?: 'hsv' | 'lab' | 'oklab'; // Space for clustering distances and hue binning (default: 'hsv')
+  useAlpha?: boolean; // Skip/weight pixels by alpha (default: false)
+  minAlpha?: number; // Alpha threshold 0-1 when useAlpha is set (default: 0.1)
 }
 
 export interface VideoConfig {
@@ -62,7 +62,8 @@ export class VideoProcessor {
           this.detector.add_video_frame(
             pixelData,
             imageData.width,
-            imageData.height
+            imageData.height,
+            timestamp
           );
         } catch (e) {
           console.warn(`Failed to extract frame at ${timestamp}s`, e);
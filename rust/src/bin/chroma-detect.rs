@@ -0,0 +1,332 @@
+//! `chroma-detect`: detect the key color of image files without a browser
+
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use chroma_detect::codec;
+use chroma_detect::detection::{ChromakeyResult, DetectionConfig, DetectionMethod, detect_chromakey};
+
+const USAGE: &str = "\
+Usage: chroma-detect [OPTIONS] <PATH>...
+
+Detects the chromakey color of each image and prints one row per file.
+Reads PPM/PGM/PAM, BMP, TGA and QOI images; directories are searched recursively for them.
+
+Options:
+  --size <WxH>            Read inputs as raw RGBA pixels of this size instead (.rgba files in directories)
+  --config <FILE>         Load DetectionConfig fields from a JSON file
+  --<field> <VALUE>       Set any DetectionConfig field, e.g. --min-saturation 0.4,
+                          --kmeans-k 4, --use-alpha true, --color-space oklab
+  --format <jsonl|csv>    Output format (default: jsonl)
+  -h, --help              Show this help
+
+Exit status: 0 if every file yielded a key, 1 if any failed, 2 on usage errors.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    JsonLines,
+    Csv,
+}
+
+#[derive(Debug)]
+struct Options {
+    config: DetectionConfig,
+    format: OutputFormat,
+    raw_size: Option<(u32, u32)>,
+    paths: Vec<PathBuf>,
+}
+
+/// None means help was requested
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut format = OutputFormat::JsonLines;
+    let mut raw_size = None;
+    let mut config_file = None;
+    let mut overrides = Vec::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(None);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            paths.push(PathBuf::from(arg));
+            continue;
+        };
+
+        // Both `--flag value` and `--flag=value`
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                let value = args.next().ok_or_else(|| format!("--{} needs a value", flag))?;
+                (flag.to_string(), value)
+            }
+        };
+
+        match name.as_str() {
+            "format" => format = parse_format(&value)?,
+            "size" => raw_size = Some(parse_size(&value)?),
+            "config" => config_file = Some(PathBuf::from(value)),
+            _ => overrides.push((name.replace('-', "_"), value)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("no input files".to_string());
+    }
+
+    let base = match config_file {
+        Some(path) => {
+            let json = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => DetectionConfig::default(),
+    };
+    let config = apply_overrides(base, &overrides)?;
+    config.validate().map_err(|e| e.to_string())?;
+
+    Ok(Some(Options { config, format, raw_size, paths }))
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, String> {
+    match value {
+        "jsonl" | "json" => Ok(OutputFormat::JsonLines),
+        "csv" => Ok(OutputFormat::Csv),
+        _ => Err(format!("unknown format '{}' (expected jsonl or csv)", value)),
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size '{}' (expected WxH, e.g. 1920x1080)", value);
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width = width.parse().map_err(|_| invalid())?;
+    let height = height.parse().map_err(|_| invalid())?;
+    Ok((width, height))
+}
+
+/// Set config fields by their snake_case names; values are read as JSON
+/// (numbers, booleans) and otherwise taken as strings (enum names)
+fn apply_overrides(config: DetectionConfig, overrides: &[(String, String)]) -> Result<DetectionConfig, String> {
+    let Value::Object(mut fields) = serde_json::to_value(config).map_err(|e| e.to_string())? else {
+        unreachable!("DetectionConfig serializes as an object");
+    };
+
+    for (name, value) in overrides {
+        if !fields.contains_key(name) {
+            return Err(format!("unknown option --{}", name.replace('_', "-")));
+        }
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()));
+        fields.insert(name.clone(), value);
+    }
+
+    serde_json::from_value(Value::Object(Map::from_iter(fields)))
+        .map_err(|e| format!("invalid option value: {}", e))
+}
+
+/// Expand directories (recursively, sorted) into the files they hold
+fn collect_files(paths: &[PathBuf], options: &Options, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            entries.sort();
+            let entries: Vec<PathBuf> = entries.into_iter()
+                .filter(|entry| entry.is_dir() || is_supported(entry, options))
+                .collect();
+            collect_files(&entries, options, files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(())
+}
+
+fn is_supported(path: &Path, options: &Options) -> bool {
+    let Some(extension) = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase) else {
+        return false;
+    };
+    match options.raw_size {
+        Some(_) => extension == "rgba",
+        None => codec::FILE_EXTENSIONS.contains(&extension.as_str()),
+    }
+}
+
+/// RGBA pixels and dimensions of one input file
+fn load_image(path: &Path, options: &Options) -> Result<(Vec<u8>, u32, u32), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    match options.raw_size {
+        Some((width, height)) => Ok((data, width, height)),
+        None => {
+            let image = codec::decode(&data).map_err(|e| e.to_string())?;
+            Ok((image.pixels, image.width, image.height))
+        }
+    }
+}
+
+fn detect_file(path: &Path, options: &Options) -> Result<ChromakeyResult, String> {
+    let (pixels, width, height) = load_image(path, options)?;
+    detect_chromakey(&pixels, width, height, &options.config).map_err(|e| e.to_string())
+}
+
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    file: &'a str,
+    #[serde(flatten)]
+    result: Option<&'a ChromakeyResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+const CSV_HEADER: &str = "file,r,g,b,hue,confidence,coverage,method,error";
+
+fn write_row(out: &mut impl Write, format: OutputFormat, file: &str, outcome: &Result<ChromakeyResult, String>) -> io::Result<()> {
+    match format {
+        OutputFormat::JsonLines => {
+            let row = JsonRow { file, result: outcome.as_ref().ok(), error: outcome.as_ref().err().map(String::as_str) };
+            serde_json::to_writer(&mut *out, &row)?;
+            writeln!(out)
+        }
+        OutputFormat::Csv => match outcome {
+            Ok(result) => writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},",
+                csv_field(file),
+                result.color.r,
+                result.color.g,
+                result.color.b,
+                result.hue,
+                result.confidence,
+                result.coverage,
+                method_name(result.method_used),
+            ),
+            Err(error) => writeln!(out, "{},,,,,,,,{}", csv_field(file), csv_field(error)),
+        },
+    }
+}
+
+fn method_name(method: DetectionMethod) -> &'static str {
+    match method {
+        DetectionMethod::Edge => "edge",
+        DetectionMethod::Cluster => "cluster",
+        DetectionMethod::Hybrid => "hybrid",
+    }
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("chroma-detect: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut files = Vec::new();
+    if let Err(e) = collect_files(&options.paths, &options, &mut files) {
+        eprintln!("chroma-detect: {}", e);
+        return ExitCode::from(2);
+    }
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut all_detected = true;
+    let written = (|| -> io::Result<()> {
+        if options.format == OutputFormat::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+        for file in &files {
+            let outcome = detect_file(file, &options);
+            all_detected &= outcome.is_ok();
+            write_row(&mut out, options.format, &file.display().to_string(), &outcome)?;
+        }
+        out.flush()
+    })();
+
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("chroma-detect: {}", e);
+            ExitCode::from(2)
+        }
+        _ if all_detected => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chroma_detect::color::{ColorSpace, RGB};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_config_flags() {
+        let options = parse_args(args("--min-saturation 0.4 --kmeans-k=4 --use-alpha true --color-space oklab a.rgba"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.config.min_saturation, 0.4);
+        assert_eq!(options.config.kmeans_k, 4);
+        assert!(options.config.use_alpha);
+        assert_eq!(options.config.color_space, ColorSpace::Oklab);
+        assert_eq!(options.paths, [PathBuf::from("a.rgba")]);
+        assert_eq!(options.format, OutputFormat::JsonLines);
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        assert!(parse_args(args("--help")).unwrap().is_none());
+        assert!(parse_args(args("")).unwrap_err().contains("no input"));
+        assert!(parse_args(args("--no-such-field 1 a")).unwrap_err().contains("--no-such-field"));
+        assert!(parse_args(args("--kmeans-k many a")).unwrap_err().contains("invalid option value"));
+        assert!(parse_args(args("--kmeans-k 0 a")).unwrap_err().contains("kmeans_k"));
+        assert!(parse_args(args("--format xml a")).is_err());
+        assert!(parse_args(args("--size 10 a")).is_err());
+        assert_eq!(parse_size("640x480"), Ok((640, 480)));
+    }
+
+    #[test]
+    fn test_rows() {
+        let result = ChromakeyResult {
+            color: RGB { r: 0, g: 255, b: 0 },
+            confidence: 0.9,
+            coverage: 0.5,
+            hue: 120.0,
+            method_used: DetectionMethod::Edge,
+        };
+        let row = |format, outcome| {
+            let mut out = Vec::new();
+            write_row(&mut out, format, "dir/a,b.rgba", &outcome).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let json = row(OutputFormat::JsonLines, Ok(result.clone()));
+        assert_eq!(
+            json,
+            "{\"file\":\"dir/a,b.rgba\",\"color\":{\"r\":0,\"g\":255,\"b\":0},\"confidence\":0.9,\"coverage\":0.5,\"hue\":120.0,\"method\":\"edge\"}\n"
+        );
+        assert_eq!(row(OutputFormat::Csv, Ok(result)), "\"dir/a,b.rgba\",0,255,0,120,0.9,0.5,edge,\n");
+        assert_eq!(
+            row(OutputFormat::Csv, Err("no \"key\"".to_string())),
+            "\"dir/a,b.rgba\",,,,,,,,\"no \"\"key\"\"\"\n"
+        );
+    }
+}
@@ -1,151 +1,583 @@
-use crate::color::{RGB, HSV};
+use serde::Serialize;
+use crate::color::{RGB, HSV, Lab, OKLab, ColorSpace, CircularStats, angular_distance};
+use crate::pixel_format::PixelSource;
 
 pub struct KMeans {
     k: usize,
     max_iterations: usize,
-    _tolerance: f32,
+    tolerance: f32,         // Stop once at most this fraction of samples change cluster
+    seed: u64,              // k-means++ seeding
+    color_space: ColorSpace,
+    min_alpha: Option<u8>,
+    auto_k: Option<(usize, usize)>, // Inclusive k range searched by silhouette
+    batch_size: Option<usize>,      // Mini-batch size for large sample sets
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub struct Cluster {
     pub centroid: HSV,
     pub size: u32,
     pub percentage: f32,
 }
 
+/// Silhouette score of one candidate k (-1 - 1, higher = better separated)
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct KScore {
+    pub k: usize,
+    pub silhouette: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ClusterReport {
+    pub clusters: Vec<Cluster>,
+    pub k: usize,                   // k actually used
+    pub k_scores: Vec<KScore>,      // Empty unless auto-k is on
+}
+
+struct Fit<P> {
+    centroids: Vec<P>,
+    assignments: Vec<usize>,
+    sizes: Vec<u32>,
+    cluster_weights: Vec<f32>,
+}
+
+// Silhouette is O(n²), so auto-k scores at most this many samples
+const AUTO_K_SAMPLES: usize = 500;
+
+// Below this many samples full k-means is cheap enough
+pub(crate) const MINI_BATCH_MIN_SAMPLES: usize = 250_000;
+// Mini-batch iterations per configured full k-means iteration
+const MINI_BATCH_ITERATION_FACTOR: usize = 10;
+// Keeps batch draws independent of the k-means++ seeding sequence
+const MINI_BATCH_SEED_SALT: u64 = 0xBA7C;
+
 impl KMeans {
     pub fn new(k: usize) -> Self {
         Self {
             k,
             max_iterations: 10,
-            _tolerance: 0.01,
+            tolerance: 0.01,
+            seed: DEFAULT_SEED,
+            color_space: ColorSpace::Hsv,
+            min_alpha: None,
+            auto_k: None,
+            batch_size: None,
         }
     }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Fraction of samples (0 - 1) allowed to change cluster in an iteration
+    /// before it counts as converged; 0 iterates until nothing moves
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Pick k from `min_k..=max_k` by silhouette score instead of using a fixed k.
+    /// `min_k` must be at least 2.
+    pub fn with_auto_k(mut self, min_k: usize, max_k: usize) -> Self {
+        self.auto_k = Some((min_k.max(2), max_k.max(min_k.max(2))));
+        self
+    }
+
+    /// Switch to mini-batch k-means (Sculley 2010) once there are more than
+    /// `MINI_BATCH_MIN_SAMPLES` samples: each iteration updates the centroids
+    /// from `batch_size` random samples instead of every sample
+    pub fn with_mini_batch(mut self, batch_size: usize) -> Self {
+        self.batch_size = (batch_size > 0).then_some(batch_size);
+        self
+    }
+
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Skip pixels whose alpha is below `min_alpha` and weight the rest by alpha
+    pub fn with_alpha_threshold(mut self, min_alpha: u8) -> Self {
+        self.min_alpha = Some(min_alpha);
+        self
+    }
     
-    pub fn find_clusters(&self, pixels: &[u8], width: u32, height: u32) -> Vec<Cluster> {
+    pub fn find_clusters<S: PixelSource>(&self, image: &S) -> Vec<Cluster> {
+        self.find_clusters_with_report(image).clusters
+    }
+
+    /// Like `find_clusters`, but also reports which k was used and, in
+    /// auto-k mode, how every candidate k scored
+    pub fn find_clusters_with_report<S: PixelSource>(&self, image: &S) -> ClusterReport {
         // Optimization: Downsample for large images
-        let sample_pixels = downsample_if_needed(pixels, width, height);
+        let sample_pixels = downsample_if_needed(image, self.min_alpha);
         
-        if sample_pixels.is_empty() {
-            return Vec::new();
+        if sample_pixels.is_empty() || self.k == 0 {
+            return ClusterReport { clusters: Vec::new(), k: self.k, k_scores: Vec::new() };
         }
 
-        // Initialize centroids (deterministic approach to avoid rand dependency)
-        // Pick k pixels evenly distributed
-        let mut centroids: Vec<HSV> = (0..self.k)
-            .map(|i| {
-                let idx = (sample_pixels.len() * (i + 1)) / (self.k + 1);
-                sample_pixels[idx].to_hsv()
-            })
-            .collect();
+        match self.color_space {
+            ColorSpace::Hsv => self.cluster_in::<HsvSpace>(&sample_pixels),
+            ColorSpace::Lab => self.cluster_in::<LabSpace>(&sample_pixels),
+            ColorSpace::Oklab => self.cluster_in::<OklabSpace>(&sample_pixels),
+        }
+    }
+
+    fn cluster_in<S: ClusterSpace>(&self, sample_pixels: &[(RGB, f32)]) -> ClusterReport {
+        // Convert once up front rather than on every iteration
+        let points: Vec<S::Point> = sample_pixels.iter().map(|(p, _)| S::from_rgb(*p)).collect();
+        let weights: Vec<f32> = sample_pixels.iter().map(|(_, w)| *w).collect();
 
-        let mut assignments = vec![0; sample_pixels.len()];
-        let mut sizes = vec![0; self.k];
+        let (k, k_scores) = match self.auto_k {
+            Some((min_k, max_k)) => self.choose_k::<S>(&points, &weights, min_k, max_k),
+            None => (self.k, Vec::new()),
+        };
+        let fit = self.fit::<S>(&points, &weights, k);
+        
+        // Convert to result structs
+        let total_weight: f32 = weights.iter().sum();
+        let mut clusters: Vec<Cluster> = fit.centroids.iter().enumerate().map(|(i, centroid)| {
+            Cluster {
+                centroid: S::to_hsv(centroid),
+                size: fit.sizes[i],
+                percentage: fit.cluster_weights[i] / total_weight,
+            }
+        }).collect();
+        
+        // Return clusters sorted by (alpha-weighted) coverage, as reported
+        clusters.sort_by(|a, b| b.percentage.total_cmp(&a.percentage));
+        ClusterReport { clusters, k, k_scores }
+    }
+
+    fn fit<S: ClusterSpace>(&self, points: &[S::Point], weights: &[f32], k: usize) -> Fit<S::Point> {
+        if let Some(batch_size) = self.batch_size {
+            if points.len() > MINI_BATCH_MIN_SAMPLES {
+                return self.fit_mini_batch::<S>(points, weights, k, batch_size);
+            }
+        }
+
+        let mut centroids = self.seed_centroids::<S>(points, weights, k);
+
+        // No sample starts in a cluster, so the first pass counts every one as a change
+        let mut assignments = vec![usize::MAX; points.len()];
+        let mut sizes = vec![0; k];
+        let mut cluster_weights = vec![0.0f32; k];
         
         for _iter in 0..self.max_iterations {
             let mut changes = 0;
             sizes.fill(0);
+            cluster_weights.fill(0.0);
             
             // Assignment step
-            for (i, pixel) in sample_pixels.iter().enumerate() {
-                let hsv = pixel.to_hsv();
-                let mut min_dist = f32::MAX;
-                let mut best_cluster = 0;
-                
-                for (c_idx, centroid) in centroids.iter().enumerate() {
-                    // Simple distance in HSV space
-                    // Focus mainly on Hue for chromakey
-                    let h_diff = (hsv.h - centroid.h).abs();
-                    let h_dist = h_diff.min(360.0 - h_diff) / 180.0; // Normalize 0-1
-                    let s_dist = (hsv.s - centroid.s).abs();
-                    let v_dist = (hsv.v - centroid.v).abs();
-                    
-                    // Weighted distance: Hue is most important
-                    let dist = h_dist * 0.6 + s_dist * 0.3 + v_dist * 0.1;
-                    
-                    if dist < min_dist {
-                        min_dist = dist;
-                        best_cluster = c_idx;
-                    }
-                }
+            for (i, point) in points.iter().enumerate() {
+                let best_cluster = nearest::<S>(point, &centroids);
                 
                 if assignments[i] != best_cluster {
                     assignments[i] = best_cluster;
                     changes += 1;
                 }
                 sizes[best_cluster] += 1;
+                cluster_weights[best_cluster] += weights[i];
             }
             
-            if changes == 0 {
+            if changes as f32 <= self.tolerance * points.len() as f32 {
                 break;
             }
             
             // Update step
-            let mut sums_h = vec![0.0; self.k];
-            let mut sums_s = vec![0.0; self.k];
-            let mut sums_v = vec![0.0; self.k];
-            let mut counts = vec![0; self.k];
-            
-            for (i, pixel) in sample_pixels.iter().enumerate() {
-                let cluster_idx = assignments[i];
-                let hsv = pixel.to_hsv();
-                sums_h[cluster_idx] += hsv.h;
-                sums_s[cluster_idx] += hsv.s;
-                sums_v[cluster_idx] += hsv.v;
-                counts[cluster_idx] += 1;
+            for (c_idx, centroid) in centroids.iter_mut().enumerate() {
+                let members = points.iter()
+                    .zip(weights)
+                    .zip(&assignments)
+                    .filter(|(_, &cluster)| cluster == c_idx)
+                    .map(|((point, &weight), _)| (point, weight));
+                if let Some(mean) = S::mean(members) {
+                    *centroid = mean;
+                }
             }
-            
-            for i in 0..self.k {
-                if counts[i] > 0 {
-                    centroids[i] = HSV {
-                        h: sums_h[i] / counts[i] as f32,
-                        s: sums_s[i] / counts[i] as f32,
-                        v: sums_v[i] / counts[i] as f32,
-                    };
+        }
+
+        Fit { centroids, assignments, sizes, cluster_weights }
+    }
+
+    /// Mini-batch k-means: every iteration draws a random batch and nudges each
+    /// sample's nearest centroid towards it, with a per-centroid learning rate
+    /// that decays as the centroid absorbs more weight. Ends with one full
+    /// assignment pass so sizes and percentages cover every sample.
+    fn fit_mini_batch<S: ClusterSpace>(
+        &self,
+        points: &[S::Point],
+        weights: &[f32],
+        k: usize,
+        batch_size: usize,
+    ) -> Fit<S::Point> {
+        let mut centroids = self.seed_centroids::<S>(points, weights, k);
+        let mut absorbed = vec![0.0f32; k];
+        let mut rng = SplitMix64::new(self.seed ^ MINI_BATCH_SEED_SALT);
+        let mut batch = Vec::with_capacity(batch_size);
+
+        for _iter in 0..self.max_iterations * MINI_BATCH_ITERATION_FACTOR {
+            // Assign the whole batch against fixed centroids, then update
+            batch.clear();
+            batch.extend((0..batch_size).map(|_| {
+                let i = rng.next_index(points.len());
+                (i, nearest::<S>(&points[i], &centroids))
+            }));
+
+            for &(i, cluster) in &batch {
+                if weights[i] <= 0.0 {
+                    continue;
                 }
+                absorbed[cluster] += weights[i];
+                let rate = weights[i] / absorbed[cluster];
+                centroids[cluster] = S::step(&centroids[cluster], &points[i], rate);
             }
         }
-        
-        // Convert to result structs
-        let total_samples = sample_pixels.len() as f32;
-        let mut clusters: Vec<Cluster> = centroids.into_iter().enumerate().map(|(i, centroid)| {
-            Cluster {
-                centroid,
-                size: sizes[i],
-                percentage: sizes[i] as f32 / total_samples,
+
+        let mut assignments = Vec::with_capacity(points.len());
+        let mut sizes = vec![0; k];
+        let mut cluster_weights = vec![0.0f32; k];
+        for (point, &weight) in points.iter().zip(weights) {
+            let cluster = nearest::<S>(point, &centroids);
+            assignments.push(cluster);
+            sizes[cluster] += 1;
+            cluster_weights[cluster] += weight;
+        }
+
+        Fit { centroids, assignments, sizes, cluster_weights }
+    }
+
+    /// Fit every k in range on an evenly spaced subsample and keep the one
+    /// with the best silhouette (ties go to the smaller k)
+    fn choose_k<S: ClusterSpace>(
+        &self,
+        points: &[S::Point],
+        weights: &[f32],
+        min_k: usize,
+        max_k: usize,
+    ) -> (usize, Vec<KScore>) {
+        let step = points.len().div_ceil(AUTO_K_SAMPLES).max(1);
+        let subsample: Vec<S::Point> = points.iter().step_by(step).copied().collect();
+        let sub_weights: Vec<f32> = weights.iter().step_by(step).copied().collect();
+
+        let scores: Vec<KScore> = (min_k..=max_k)
+            .map(|k| {
+                let fit = self.fit::<S>(&subsample, &sub_weights, k);
+                KScore { k, silhouette: silhouette::<S>(&subsample, &fit.assignments, k) }
+            })
+            .collect();
+
+        let best = scores.iter()
+            .fold(None::<&KScore>, |best, score| match best {
+                Some(b) if b.silhouette >= score.silhouette => Some(b),
+                _ => Some(score),
+            })
+            .map_or(min_k, |score| score.k);
+        (best, scores)
+    }
+
+    /// k-means++: the first centroid is a random sample, each next one is drawn
+    /// with probability proportional to its squared distance from the
+    /// nearest centroid so far (times its weight)
+    fn seed_centroids<S: ClusterSpace>(&self, points: &[S::Point], weights: &[f32], k: usize) -> Vec<S::Point> {
+        let mut rng = SplitMix64::new(self.seed);
+        let mut centroids = Vec::with_capacity(k);
+        centroids.push(points[rng.next_index(points.len())]);
+
+        let mut nearest: Vec<f32> = points.iter()
+            .map(|p| S::distance(p, &centroids[0]))
+            .collect();
+
+        while centroids.len() < k {
+            let scores: Vec<f32> = nearest.iter().zip(weights).map(|(d, w)| d * d * w).collect();
+            let total: f32 = scores.iter().sum();
+
+            // Fewer distinct colors than k: any sample will do
+            let next = if total > 0.0 {
+                let mut target = rng.next_f32() * total;
+                scores.iter()
+                    .position(|&score| {
+                        target -= score;
+                        target < 0.0
+                    })
+                    .unwrap_or_else(|| scores.iter().rposition(|&score| score > 0.0).unwrap_or(0))
+            } else {
+                rng.next_index(points.len())
+            };
+
+            let centroid = points[next];
+            for (d, p) in nearest.iter_mut().zip(points) {
+                *d = d.min(S::distance(p, &centroid));
             }
-        }).collect();
-        
-        // Return clusters sorted by size
-        clusters.sort_by(|a, b| b.size.cmp(&a.size));
-        clusters
+            centroids.push(centroid);
+        }
+
+        centroids
     }
 }
 
-fn downsample_if_needed(pixels: &[u8], width: u32, height: u32) -> Vec<RGB> {
-    let _total_pixels = (width * height) as usize;
-    // Pixels are RGBA (4 bytes)
-    let pixel_count = pixels.len() / 4;
-    
+fn nearest<S: ClusterSpace>(point: &S::Point, centroids: &[S::Point]) -> usize {
+    let mut min_dist = f32::MAX;
+    let mut best_cluster = 0;
+    for (c_idx, centroid) in centroids.iter().enumerate() {
+        let dist = S::distance(point, centroid);
+        if dist < min_dist {
+            min_dist = dist;
+            best_cluster = c_idx;
+        }
+    }
+    best_cluster
+}
+
+/// Mean silhouette over all points: how much closer each point is to its own
+/// cluster than to the nearest other one. Singletons and empty clusters score 0.
+fn silhouette<S: ClusterSpace>(points: &[S::Point], assignments: &[usize], k: usize) -> f32 {
+    if points.is_empty() {
+        return 0.0;
+    }
+
+    let mut sizes = vec![0usize; k];
+    for &cluster in assignments {
+        sizes[cluster] += 1;
+    }
+
+    let mut sums = vec![0.0f32; k];
+    let mut total = 0.0;
+    for (i, point) in points.iter().enumerate() {
+        let own = assignments[i];
+        if sizes[own] <= 1 {
+            continue;
+        }
+
+        sums.fill(0.0);
+        for (other, &cluster) in points.iter().zip(assignments) {
+            sums[cluster] += S::distance(point, other);
+        }
+
+        let a = sums[own] / (sizes[own] - 1) as f32;
+        let b = (0..k)
+            .filter(|&c| c != own && sizes[c] > 0)
+            .map(|c| sums[c] / sizes[c] as f32)
+            .fold(f32::INFINITY, f32::min);
+        if b.is_finite() && a.max(b) > 0.0 {
+            total += (b - a) / a.max(b);
+        }
+    }
+
+    total / points.len() as f32
+}
+
+const DEFAULT_SEED: u64 = 0x5EED;
+
+/// SplitMix64: tiny, fast and good enough for seeding k-means, and keeps
+/// results reproducible without a rand dependency
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn next_index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+/// A color space k-means can run in: how to embed pixels, measure
+/// distance and average a cluster
+trait ClusterSpace {
+    type Point: Copy;
+
+    fn from_rgb(rgb: RGB) -> Self::Point;
+    fn distance(a: &Self::Point, b: &Self::Point) -> f32;
+    /// Weighted mean of (point, weight) pairs; None if the total weight is zero
+    fn mean<'a>(points: impl Iterator<Item = (&'a Self::Point, f32)>) -> Option<Self::Point>
+    where
+        Self::Point: 'a;
+    fn to_hsv(point: &Self::Point) -> HSV;
+    /// Move `from` a fraction `rate` (0 - 1) of the way towards `to`
+    fn step(from: &Self::Point, to: &Self::Point, rate: f32) -> Self::Point;
+}
+
+struct HsvSpace;
+struct LabSpace;
+struct OklabSpace;
+
+impl ClusterSpace for HsvSpace {
+    type Point = HSV;
+
+    fn from_rgb(rgb: RGB) -> HSV {
+        rgb.to_hsv()
+    }
+
+    fn distance(a: &HSV, b: &HSV) -> f32 {
+        // Simple distance in HSV space
+        // Focus mainly on Hue for chromakey
+        let h_dist = angular_distance(a.h, b.h) / 180.0; // Normalize 0-1
+        let s_dist = (a.s - b.s).abs();
+        let v_dist = (a.v - b.v).abs();
+
+        // Weighted distance: Hue is most important
+        h_dist * 0.6 + s_dist * 0.3 + v_dist * 0.1
+    }
+
+    fn mean<'a>(points: impl Iterator<Item = (&'a HSV, f32)>) -> Option<HSV> {
+        // Hue is an angle: red spanning 355° and 5° must average to 0°, not 180°
+        let mut hue = CircularStats::new();
+        let (mut s, mut v, mut total) = (0.0, 0.0, 0.0);
+        for (p, w) in points {
+            hue.add_weighted(p.h, w);
+            s += p.s * w;
+            v += p.v * w;
+            total += w;
+        }
+        (total > 0.0).then(|| HSV {
+            h: hue.mean().unwrap_or(0.0),
+            s: s / total,
+            v: v / total,
+        })
+    }
+
+    fn to_hsv(point: &HSV) -> HSV {
+        *point
+    }
+
+    fn step(from: &HSV, to: &HSV, rate: f32) -> HSV {
+        // Along the shorter arc, so red centroids don't swing through cyan
+        let hue_diff = (to.h - from.h + 180.0).rem_euclid(360.0) - 180.0;
+        HSV {
+            h: (from.h + hue_diff * rate).rem_euclid(360.0),
+            s: from.s + (to.s - from.s) * rate,
+            v: from.v + (to.v - from.v) * rate,
+        }
+    }
+}
+
+impl ClusterSpace for LabSpace {
+    type Point = Lab;
+
+    fn from_rgb(rgb: RGB) -> Lab {
+        rgb.to_lab()
+    }
+
+    fn distance(a: &Lab, b: &Lab) -> f32 {
+        a.delta_e_2000(b)
+    }
+
+    fn mean<'a>(points: impl Iterator<Item = (&'a Lab, f32)>) -> Option<Lab> {
+        let (mut l, mut a, mut b, mut total) = (0.0, 0.0, 0.0, 0.0);
+        for (p, w) in points {
+            l += p.l * w;
+            a += p.a * w;
+            b += p.b * w;
+            total += w;
+        }
+        (total > 0.0).then(|| Lab { l: l / total, a: a / total, b: b / total })
+    }
+
+    fn to_hsv(point: &Lab) -> HSV {
+        point.to_rgb().to_hsv()
+    }
+
+    fn step(from: &Lab, to: &Lab, rate: f32) -> Lab {
+        Lab {
+            l: from.l + (to.l - from.l) * rate,
+            a: from.a + (to.a - from.a) * rate,
+            b: from.b + (to.b - from.b) * rate,
+        }
+    }
+}
+
+impl ClusterSpace for OklabSpace {
+    type Point = OKLab;
+
+    fn from_rgb(rgb: RGB) -> OKLab {
+        rgb.to_oklab()
+    }
+
+    fn distance(a: &OKLab, b: &OKLab) -> f32 {
+        a.distance(b)
+    }
+
+    fn mean<'a>(points: impl Iterator<Item = (&'a OKLab, f32)>) -> Option<OKLab> {
+        let (mut l, mut a, mut b, mut total) = (0.0, 0.0, 0.0, 0.0);
+        for (p, w) in points {
+            l += p.l * w;
+            a += p.a * w;
+            b += p.b * w;
+            total += w;
+        }
+        (total > 0.0).then(|| OKLab { l: l / total, a: a / total, b: b / total })
+    }
+
+    fn to_hsv(point: &OKLab) -> HSV {
+        point.to_rgb().to_hsv()
+    }
+
+    fn step(from: &OKLab, to: &OKLab, rate: f32) -> OKLab {
+        OKLab {
+            l: from.l + (to.l - from.l) * rate,
+            a: from.a + (to.a - from.a) * rate,
+            b: from.b + (to.b - from.b) * rate,
+        }
+    }
+}
+
+/// Pixel step used when sampling an image of `pixel_count` pixels for clustering
+pub fn sample_stride(pixel_count: usize) -> usize {
     // For images > 1MP, sample every 4th pixel
     // Or if we have a huge image, we want to limit to ~250k pixels for speed
-    let step = if pixel_count > 1_000_000 {
+    if pixel_count > 1_000_000 {
         4
     } else {
         1
-    };
+    }
+}
+
+/// Sampled pixels with their weights. Without an alpha threshold every
+/// pixel weighs 1; with one, more transparent pixels are dropped and the
+/// rest weigh alpha / 255.
+fn downsample_if_needed<S: PixelSource>(image: &S, min_alpha: Option<u8>) -> Vec<(RGB, f32)> {
+    let pixel_count = image.pixel_count();
+    let width = image.width() as usize;
+    let step = sample_stride(pixel_count);
     
     let mut sampled = Vec::with_capacity(pixel_count / step);
     
     for i in (0..pixel_count).step_by(step) {
-        let idx = i * 4;
-        if idx + 2 < pixels.len() {
-            sampled.push(RGB {
-                r: pixels[idx],
-                g: pixels[idx + 1],
-                b: pixels[idx + 2],
-            });
-        }
+        let (x, y) = ((i % width) as u32, (i / width) as u32);
+        let weight = match min_alpha {
+            None => 1.0,
+            Some(min_alpha) => {
+                let alpha = image.alpha(x, y);
+                if alpha == 0 || alpha < min_alpha {
+                    continue;
+                }
+                alpha as f32 / 255.0
+            }
+        };
+        sampled.push((image.pixel(x, y), weight));
     }
     
     sampled
@@ -154,6 +586,7 @@ fn downsample_if_needed(pixels: &[u8], width: u32, height: u32) -> Vec<RGB> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pixel_format::ImageView;
 
     #[test]
     fn test_kmeans_simple_clustering() {
@@ -169,7 +602,7 @@ mod tests {
         // Total 150 pixels
         
         let kmeans = KMeans::new(2);
-        let clusters = kmeans.find_clusters(&pixels, 150, 1);
+        let clusters = kmeans.find_clusters(&ImageView::rgba(&pixels, 150, 1).unwrap());
         
         assert_eq!(clusters.len(), 2);
         
@@ -182,6 +615,30 @@ mod tests {
         assert_eq!(clusters[1].size, 50);
     }
 
+    #[test]
+    fn test_kmeans_perceptual_spaces() {
+        let mut pixels = Vec::new();
+        // 100 green pixels with slight lighting variation
+        for i in 0..100u8 {
+            pixels.extend_from_slice(&[10, 200 + i % 20, 30, 255]);
+        }
+        // 50 blue pixels
+        for _ in 0..50 {
+            pixels.extend_from_slice(&[0, 0, 255, 255]);
+        }
+
+        for space in [ColorSpace::Lab, ColorSpace::Oklab] {
+            let kmeans = KMeans::new(2).with_color_space(space);
+            let clusters = kmeans.find_clusters(&ImageView::rgba(&pixels, 150, 1).unwrap());
+
+            assert_eq!(clusters.len(), 2);
+            assert_eq!(clusters[0].size, 100, "{:?}", space);
+            assert!((clusters[0].centroid.h - 126.0).abs() < 5.0, "{:?}: {}", space, clusters[0].centroid.h);
+            assert_eq!(clusters[1].size, 50);
+            assert!((clusters[1].centroid.h - 240.0).abs() < 5.0);
+        }
+    }
+
     #[test]
     fn test_downsampling() {
         // Create 10 pixels
@@ -203,9 +660,259 @@ mod tests {
         // Since we can't inject it easily without changing code, we'll skip the heavy downsample test and rely on `clustering.rs` logic review.
         // Instead, we verify `downsample_if_needed` works for small inputs (step=1).
         
-        let sampled = downsample_if_needed(&pixels, 10, 1);
+        let sampled = downsample_if_needed(&ImageView::rgba(&pixels, 10, 1).unwrap(), None);
         assert_eq!(sampled.len(), 10);
-        assert_eq!(sampled[0].r, 0);
-        assert_eq!(sampled[9].r, 9);
+        assert_eq!(sampled[0].0.r, 0);
+        assert_eq!(sampled[9].0.r, 9);
+    }
+
+    #[test]
+    fn test_alpha_weighting() {
+        let mut pixels = Vec::new();
+        // 50 opaque green pixels
+        for _ in 0..50 {
+            pixels.extend_from_slice(&[0, 255, 0, 255]);
+        }
+        // 100 transparent blue pixels that shouldn't vote
+        for _ in 0..100 {
+            pixels.extend_from_slice(&[0, 0, 255, 0]);
+        }
+        // 50 half-transparent red pixels
+        for _ in 0..50 {
+            pixels.extend_from_slice(&[255, 0, 0, 128]);
+        }
+        let image = ImageView::rgba(&pixels, 200, 1).unwrap();
+
+        let sampled = downsample_if_needed(&image, Some(26));
+        assert_eq!(sampled.len(), 100);
+        assert!((sampled[99].1 - 128.0 / 255.0).abs() < 1e-6);
+
+        let clusters = KMeans::new(2).with_alpha_threshold(26).find_clusters(&image);
+        let green = clusters.iter().find(|c| (c.centroid.h - 120.0).abs() < 5.0).unwrap();
+        assert_eq!(green.size, 50);
+        // Green carries 255 / (255 + 128) of the weight
+        assert!((green.percentage - 255.0 / 383.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_clusters_sorted_by_weight() {
+        let mut pixels = Vec::new();
+        // 40 opaque green pixels outweigh 60 faint red ones
+        for _ in 0..40 {
+            pixels.extend_from_slice(&[0, 255, 0, 255]);
+        }
+        for _ in 0..60 {
+            pixels.extend_from_slice(&[255, 0, 0, 100]);
+        }
+        let image = ImageView::rgba(&pixels, 100, 1).unwrap();
+
+        let clusters = KMeans::new(2).with_alpha_threshold(26).find_clusters(&image);
+        assert!((clusters[0].centroid.h - 120.0).abs() < 5.0, "{:?}", clusters);
+        assert_eq!((clusters[0].size, clusters[1].size), (40, 60));
+        assert!(clusters[0].percentage > clusters[1].percentage);
+    }
+
+    #[test]
+    fn test_kmeans_red_wraps_hue() {
+        let mut pixels = Vec::new();
+        // 100 red pixels on both sides of 0° (hues ~357.6° and ~2.4°)
+        for i in 0..100 {
+            if i % 2 == 0 {
+                pixels.extend_from_slice(&[255, 0, 10, 255]);
+            } else {
+                pixels.extend_from_slice(&[255, 10, 0, 255]);
+            }
+        }
+        // 50 magenta pixels (300°)
+        for _ in 0..50 {
+            pixels.extend_from_slice(&[255, 0, 255, 255]);
+        }
+
+        let clusters = KMeans::new(2).find_clusters(&ImageView::rgba(&pixels, 150, 1).unwrap());
+
+        assert_eq!(clusters[0].size, 100);
+        assert!(angular_distance(clusters[0].centroid.h, 0.0) < 1.0, "red centroid = {}", clusters[0].centroid.h);
+        assert!((clusters[1].centroid.h - 300.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_seeded_plusplus_init() {
+        let mut pixels = Vec::new();
+        for i in 0..300u32 {
+            match i % 3 {
+                0 => pixels.extend_from_slice(&[0, 255, 0, 255]),
+                1 => pixels.extend_from_slice(&[0, 0, 255, 255]),
+                _ => pixels.extend_from_slice(&[255, 0, 0, 255]),
+            }
+        }
+        let image = ImageView::rgba(&pixels, 300, 1).unwrap();
+
+        // k-means++ spreads the seeds, so every color gets its own cluster
+        for seed in [1, 2, 3, 42] {
+            let clusters = KMeans::new(3).with_seed(seed).find_clusters(&image);
+            assert!(clusters.iter().all(|c| c.size == 100), "seed {}: {:?}", seed, clusters);
+        }
+
+        // Same seed, same answer
+        let a = KMeans::new(2).with_seed(7).find_clusters(&image);
+        let b = KMeans::new(2).with_seed(7).find_clusters(&image);
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!((a.size, a.centroid.h), (b.size, b.centroid.h));
+        }
+    }
+
+    #[test]
+    fn test_tolerance_stops_early() {
+        let mut pixels = Vec::new();
+        for i in 0..200u8 {
+            pixels.extend_from_slice(&[i, 255 - i, 128, 255]);
+        }
+        let image = ImageView::rgba(&pixels, 200, 1).unwrap();
+
+        // Tolerance 1 accepts the first assignment; the result is still a partition
+        let clusters = KMeans::new(4).with_tolerance(1.0).find_clusters(&image);
+        assert_eq!(clusters.iter().map(|c| c.size).sum::<u32>(), 200);
+
+        let clusters = KMeans::new(4).with_tolerance(0.0).with_max_iterations(50).find_clusters(&image);
+        assert_eq!(clusters.len(), 4);
+        assert!((clusters.iter().map(|c| c.percentage).sum::<f32>() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_splitmix_reproducible() {
+        let mut a = SplitMix64::new(99);
+        let mut b = SplitMix64::new(99);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+            let f = a.next_f32();
+            assert!((0.0..1.0).contains(&f));
+            b.next_f32();
+        }
+        assert!(SplitMix64::new(1).next_u64() != SplitMix64::new(2).next_u64());
+    }
+
+    #[test]
+    fn test_auto_k_picks_distinct_colors() {
+        let colors: [[u8; 3]; 4] = [[0, 255, 0], [0, 0, 255], [255, 0, 0], [250, 250, 0]];
+        let mut pixels = Vec::new();
+        for i in 0..400usize {
+            let c = colors[i % 4];
+            // Small variation so clusters aren't single points
+            let jitter = (i / 4 % 5) as u8 * 3;
+            pixels.extend_from_slice(&[c[0].saturating_sub(jitter), c[1].saturating_sub(jitter), c[2], 255]);
+        }
+        let image = ImageView::rgba(&pixels, 400, 1).unwrap();
+
+        let report = KMeans::new(3).with_auto_k(2, 6).find_clusters_with_report(&image);
+
+        assert_eq!(report.k, 4, "{:?}", report.k_scores);
+        assert_eq!(report.clusters.len(), 4);
+        assert_eq!(report.k_scores.iter().map(|s| s.k).collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+        let best = report.k_scores.iter().find(|s| s.k == 4).unwrap();
+        assert!(report.k_scores.iter().all(|s| s.silhouette <= best.silhouette));
+
+        // Fixed k reports no scores
+        let fixed = KMeans::new(3).find_clusters_with_report(&image);
+        assert_eq!(fixed.k, 3);
+        assert!(fixed.k_scores.is_empty());
+    }
+
+    #[test]
+    fn test_silhouette_bounds() {
+        let points: Vec<HSV> = [0.0, 2.0, 180.0, 182.0]
+            .iter()
+            .map(|&h| HSV { h, s: 1.0, v: 1.0 })
+            .collect();
+
+        let good = silhouette::<HsvSpace>(&points, &[0, 0, 1, 1], 2);
+        let bad = silhouette::<HsvSpace>(&points, &[0, 1, 0, 1], 2);
+        assert!(good > 0.9, "good = {}", good);
+        assert!(bad < 0.0, "bad = {}", bad);
+        assert_eq!(silhouette::<HsvSpace>(&points, &[0, 0, 0, 0], 2), 0.0);
+    }
+
+    #[test]
+    fn test_mini_batch_matches_full() {
+        // Three noisy blobs, including a red one straddling 0°
+        let mut rng = SplitMix64::new(5);
+        let centers: [([u8; 3], usize); 3] = [([20, 200, 40], 6000), ([30, 50, 210], 3000), ([220, 10, 10], 1000)];
+        let mut samples = Vec::new();
+        for (color, count) in centers {
+            for _ in 0..count {
+                let jitter = |c: u8, rng: &mut SplitMix64| (c as i32 + rng.next_index(21) as i32 - 10).clamp(0, 255) as u8;
+                let rgb = RGB { r: jitter(color[0], &mut rng), g: jitter(color[1], &mut rng), b: jitter(color[2], &mut rng) };
+                samples.push((rgb, 1.0));
+            }
+        }
+
+        let kmeans = KMeans::new(3).with_mini_batch(256);
+        for space in [ColorSpace::Hsv, ColorSpace::Oklab] {
+            let (full, mini) = match space {
+                ColorSpace::Hsv => compare_fits::<HsvSpace>(&kmeans, &samples),
+                _ => compare_fits::<OklabSpace>(&kmeans, &samples),
+            };
+
+            for (f, m) in full.iter().zip(&mini) {
+                assert!(angular_distance(f.centroid.h, m.centroid.h) < 3.0, "{:?}: {:?} vs {:?}", space, f, m);
+                assert!((f.centroid.s - m.centroid.s).abs() < 0.05);
+                assert!((f.percentage - m.percentage).abs() < 0.01, "{:?}: {:?} vs {:?}", space, f, m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mini_batch_threshold() {
+        // Noisy green/blue halves; 300k pixels sample at stride 1, past the threshold
+        let noisy_image = |width: u32, height: u32| {
+            let mut rng = SplitMix64::new(9);
+            let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+            for _ in 0..height {
+                for x in 0..width {
+                    let base: [u8; 3] = if x < width / 2 { [30, 190, 50] } else { [40, 60, 200] };
+                    pixels.extend(base.map(|c| c + rng.next_index(20) as u8));
+                    pixels.push(255);
+                }
+            }
+            pixels
+        };
+        let centroids = |clusters: Vec<Cluster>| clusters.iter().map(|c| (c.centroid.h, c.size)).collect::<Vec<_>>();
+        let mini_batch = KMeans::new(3).with_mini_batch(256);
+
+        let pixels = noisy_image(600, 500);
+        let image = ImageView::rgba(&pixels, 600, 500).unwrap();
+        let samples = downsample_if_needed(&image, None);
+        assert!(samples.len() > MINI_BATCH_MIN_SAMPLES);
+        let (full, mini) = compare_fits::<HsvSpace>(&mini_batch, &samples);
+        assert_eq!(centroids(mini_batch.find_clusters(&image)), centroids(mini));
+        assert_eq!(centroids(KMeans::new(3).find_clusters(&image)), centroids(full));
+
+        // Below the threshold the batch size is ignored
+        let pixels = noisy_image(500, 400);
+        let image = ImageView::rgba(&pixels, 500, 400).unwrap();
+        assert_eq!(
+            centroids(mini_batch.find_clusters(&image)),
+            centroids(KMeans::new(3).find_clusters(&image))
+        );
+    }
+
+    /// Full and mini-batch clusters for the same samples, largest first
+    fn compare_fits<S: ClusterSpace>(kmeans: &KMeans, samples: &[(RGB, f32)]) -> (Vec<Cluster>, Vec<Cluster>) {
+        let points: Vec<S::Point> = samples.iter().map(|(p, _)| S::from_rgb(*p)).collect();
+        let weights: Vec<f32> = samples.iter().map(|(_, w)| *w).collect();
+        let total = weights.len() as f32;
+
+        let summarize = |fit: Fit<S::Point>| {
+            let mut clusters: Vec<Cluster> = fit.centroids.iter().enumerate().map(|(i, c)| Cluster {
+                centroid: S::to_hsv(c),
+                size: fit.sizes[i],
+                percentage: fit.cluster_weights[i] / total,
+            }).collect();
+            clusters.sort_by_key(|c| std::cmp::Reverse(c.size));
+            clusters
+        };
+
+        let full = KMeans { batch_size: None, ..*kmeans }.fit::<S>(&points, &weights, 3);
+        let mini = kmeans.fit_mini_batch::<S>(&points, &weights, 3, 256);
+        (summarize(full), summarize(mini))
     }
 }
@@ -0,0 +1,380 @@
+//! Windows BMP: uncompressed 1/4/8-bit palette and 16/24/32-bit true color
+//! images (with BI_BITFIELDS channel masks and alpha), and RLE8/RLE4
+//! compression. Rows may be stored bottom-up (the usual) or top-down.
+
+use super::{DecodeError, Image, invalid_header, u16_le, u32_le};
+
+// Compression methods
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+const FILE_HEADER_LEN: usize = 14;
+// OS/2 BITMAPCOREHEADER: 16-bit dimensions and 3-byte palette entries
+const CORE_HEADER_LEN: usize = 12;
+// Offset of the channel masks, which follow a 40-byte BITMAPINFOHEADER or
+// sit at the same place inside the larger V2 - V5 headers
+const MASKS_OFFSET: usize = FILE_HEADER_LEN + 40;
+
+struct Header {
+    info_len: usize,   // Size of the DIB header, which identifies its version
+    width: u32,
+    height: u32,
+    top_down: bool,
+    bits: u16,         // Bits per pixel
+    compression: u32,
+    pixel_offset: usize,
+}
+
+impl Header {
+    /// Image row of the `index`th row stored in the file
+    fn row_y(&self, index: u32) -> u32 {
+        if self.top_down { index } else { self.height - 1 - index }
+    }
+}
+
+/// True if `data` starts with the BMP signature
+pub fn is_bmp(data: &[u8]) -> bool {
+    data.starts_with(b"BM")
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !is_bmp(data) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let header = read_header(data)?;
+    let palette = if header.bits <= 8 { read_palette(data, &header)? } else { Vec::new() };
+    match header.compression {
+        BI_RLE8 | BI_RLE4 => decode_rle(data, &header, &palette),
+        _ => decode_rows(data, &header, &palette),
+    }
+}
+
+fn read_header(data: &[u8]) -> Result<Header, DecodeError> {
+    let pixel_offset = u32_le(data, 10)? as usize;
+    let info_len = u32_le(data, FILE_HEADER_LEN)? as usize;
+    let (width, height, bits, compression) = match info_len {
+        CORE_HEADER_LEN => (u16_le(data, 18)? as i32, u16_le(data, 20)? as i32, u16_le(data, 24)?, BI_RGB),
+        40 | 52 | 56 | 108 | 124 => (
+            u32_le(data, 18)? as i32,
+            u32_le(data, 22)? as i32,
+            u16_le(data, 28)?,
+            u32_le(data, 30)?,
+        ),
+        _ => return Err(DecodeError::Unsupported { feature: format!("BMP header of {} bytes", info_len) }),
+    };
+
+    match (compression, bits) {
+        (BI_RGB, 1 | 4 | 8 | 16 | 24 | 32) | (BI_RLE8, 8) | (BI_RLE4, 4) => {}
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {}
+        (BI_RGB | BI_RLE8 | BI_RLE4 | BI_BITFIELDS | BI_ALPHABITFIELDS, _) => {
+            return Err(invalid_header(format!("{} bits per pixel with compression {}", bits, compression)));
+        }
+        (4, _) => return Err(DecodeError::Unsupported { feature: "JPEG-compressed BMP".to_string() }),
+        (5, _) => return Err(DecodeError::Unsupported { feature: "PNG-compressed BMP".to_string() }),
+        _ => return Err(DecodeError::Unsupported { feature: format!("BMP compression {}", compression) }),
+    }
+
+    if width <= 0 || height == 0 {
+        return Err(invalid_header(format!("invalid dimensions {}x{}", width, height)));
+    }
+    // Negative height means rows run top to bottom, which RLE doesn't allow
+    let top_down = height < 0;
+    if top_down && matches!(compression, BI_RLE8 | BI_RLE4) {
+        return Err(invalid_header("RLE bitmaps must be stored bottom-up".to_string()));
+    }
+
+    Ok(Header {
+        info_len,
+        width: width as u32,
+        height: height.unsigned_abs(),
+        top_down,
+        bits,
+        compression,
+        pixel_offset,
+    })
+}
+
+/// Color table of a 1/4/8-bit image, as RGBA
+fn read_palette(data: &[u8], header: &Header) -> Result<Vec<[u8; 4]>, DecodeError> {
+    let core = header.info_len == CORE_HEADER_LEN;
+    let entry_len = if core { 3 } else { 4 };
+    // Zero (and the core header, which has no such field) means a full table
+    let colors_used = if core { 0 } else { u32_le(data, 46)? as usize };
+    let count = if colors_used == 0 { 1 << header.bits } else { colors_used.min(256) };
+
+    let start = FILE_HEADER_LEN + header.info_len;
+    let end = start + count * entry_len;
+    let table = data.get(start..end).ok_or(DecodeError::Truncated { expected: end, actual: data.len() })?;
+    Ok(table.chunks_exact(entry_len).map(|bgr| [bgr[2], bgr[1], bgr[0], 255]).collect())
+}
+
+fn palette_color(palette: &[[u8; 4]], index: u8) -> Result<[u8; 4], DecodeError> {
+    palette.get(index as usize).copied().ok_or_else(|| DecodeError::InvalidData {
+        message: format!("palette index {} is past the {}-color table", index, palette.len()),
+    })
+}
+
+/// Red, green, blue and alpha masks of a 16/32-bit pixel; no alpha mask
+/// means the image is opaque
+fn channel_masks(data: &[u8], header: &Header) -> Result<[u32; 4], DecodeError> {
+    match header.compression {
+        BI_BITFIELDS | BI_ALPHABITFIELDS => {
+            let has_alpha = header.compression == BI_ALPHABITFIELDS || header.info_len >= 56;
+            Ok([
+                u32_le(data, MASKS_OFFSET)?,
+                u32_le(data, MASKS_OFFSET + 4)?,
+                u32_le(data, MASKS_OFFSET + 8)?,
+                if has_alpha { u32_le(data, MASKS_OFFSET + 12)? } else { 0 },
+            ])
+        }
+        // 16-bit BI_RGB is 5-5-5
+        _ if header.bits == 16 => Ok([0x7C00, 0x03E0, 0x001F, 0]),
+        _ => Ok([0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000]),
+    }
+}
+
+/// Value of the bits under `mask`, rescaled to 0 - 255
+fn masked_channel(value: u32, mask: u32) -> u8 {
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let raw = ((value & mask) >> shift) as u64;
+    ((raw * 255 + max / 2) / max) as u8
+}
+
+fn masked_pixel(value: u32, masks: &[u32; 4]) -> [u8; 4] {
+    let channel = |mask: u32| if mask == 0 { 0 } else { masked_channel(value, mask) };
+    let alpha = if masks[3] == 0 { 255 } else { masked_channel(value, masks[3]) };
+    [channel(masks[0]), channel(masks[1]), channel(masks[2]), alpha]
+}
+
+/// Uncompressed rows, each padded to a multiple of 4 bytes
+fn decode_rows(data: &[u8], header: &Header, palette: &[[u8; 4]]) -> Result<Image, DecodeError> {
+    let bits = header.bits as usize;
+    let stride = (header.width as usize * bits).div_ceil(32) * 4;
+    let raster = data.get(header.pixel_offset..).unwrap_or_default();
+    let expected = stride as u64 * header.height as u64;
+    if (raster.len() as u64) < expected {
+        return Err(DecodeError::Truncated { expected: expected as usize, actual: raster.len() });
+    }
+    let masks = channel_masks(data, header)?;
+
+    let mut image = Image::blank(header.width, header.height)?;
+    for (index, row) in raster.chunks_exact(stride).take(header.height as usize).enumerate() {
+        let y = header.row_y(index as u32);
+        for x in 0..header.width as usize {
+            let rgba = match bits {
+                1 | 4 | 8 => {
+                    let bit = x * bits;
+                    let index = (row[bit / 8] >> (8 - bits - bit % 8)) & ((1u16 << bits) - 1) as u8;
+                    palette_color(palette, index)?
+                }
+                16 => masked_pixel(u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32, &masks),
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                _ => {
+                    let bytes = &row[x * 4..x * 4 + 4];
+                    masked_pixel(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]), &masks)
+                }
+            };
+            image.set(x as u32, y, rgba);
+        }
+    }
+
+    // Most 32-bit BI_RGB files leave the fourth byte zero rather than
+    // storing alpha, so an entirely transparent image is really opaque
+    if header.compression == BI_RGB && header.bits == 32 && image.pixels.chunks_exact(4).all(|p| p[3] == 0) {
+        image.pixels.chunks_exact_mut(4).for_each(|p| p[3] = 255);
+    }
+    Ok(image)
+}
+
+/// RLE8/RLE4: runs of one palette index, absolute runs of literal indices,
+/// and escapes for end of line, end of bitmap and cursor jumps. Pixels the
+/// cursor jumps over are left transparent.
+fn decode_rle(data: &[u8], header: &Header, palette: &[[u8; 4]]) -> Result<Image, DecodeError> {
+    let four_bit = header.compression == BI_RLE4;
+    // Index `i` of a run whose bytes hold one index, or two nibbles
+    let index_at = |bytes: &[u8], i: usize| {
+        if !four_bit {
+            bytes[i]
+        } else if i.is_multiple_of(2) {
+            bytes[i / 2] >> 4
+        } else {
+            bytes[i / 2] & 0x0F
+        }
+    };
+    let take = |pos: usize, len: usize| {
+        data.get(pos..pos + len).ok_or(DecodeError::Truncated { expected: pos + len, actual: data.len() })
+    };
+
+    let mut image = Image::blank(header.width, header.height)?;
+    let mut put = |x: u32, row: u32, index: u8| -> Result<(), DecodeError> {
+        // Runs past the right edge are clipped
+        if x < header.width && row < header.height {
+            image.set(x, header.row_y(row), palette_color(palette, index)?);
+        }
+        Ok(())
+    };
+
+    let (mut x, mut row) = (0u32, 0u32);
+    let mut pos = header.pixel_offset;
+    // A missing end-of-bitmap marker is tolerated
+    while let Some(&[count, value]) = data.get(pos..pos + 2) {
+        pos += 2;
+        if row >= header.height {
+            break;
+        }
+        match (count, value) {
+            (0, 0) => {
+                x = 0;
+                row += 1;
+            }
+            (0, 1) => break,
+            (0, 2) => {
+                let delta = take(pos, 2)?;
+                x = x.saturating_add(delta[0] as u32);
+                row = row.saturating_add(delta[1] as u32);
+                pos += 2;
+            }
+            (0, literal) => {
+                let literal = literal as usize;
+                let len = if four_bit { literal.div_ceil(2) } else { literal };
+                let bytes = take(pos, len)?;
+                for i in 0..literal {
+                    put(x, row, index_at(bytes, i))?;
+                    x = x.saturating_add(1);
+                }
+                // Absolute runs are padded to a 16-bit boundary
+                pos += len + len % 2;
+            }
+            (count, _) => {
+                let run = [value; 2];
+                for i in 0..count as usize {
+                    put(x, row, index_at(&run, i % 2))?;
+                    x = x.saturating_add(1);
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BMP with a 40-byte BITMAPINFOHEADER; `table` (masks or palette)
+    /// goes between the header and the pixels
+    fn bmp(width: i32, height: i32, bits: u16, compression: u32, table: &[u8], pixels: &[u8]) -> Vec<u8> {
+        let offset = (54 + table.len()) as u32;
+        let colors_used = if bits <= 8 { table.len() as u32 / 4 } else { 0 };
+        let mut data = b"BM".to_vec();
+        for value in [offset + pixels.len() as u32, 0, offset, 40] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bits.to_le_bytes());
+        for value in [compression, pixels.len() as u32, 2835, 2835, colors_used, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(table);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    // Blue, green, red (BGRx)
+    const PALETTE: [u8; 12] = [255, 0, 0, 0, 0, 255, 0, 0, 0, 0, 255, 0];
+
+    #[test]
+    fn test_24_bit_bottom_up() {
+        // Rows of 2 pixels are 6 bytes plus 2 of padding; the bottom row comes first
+        let pixels = [0, 0, 255, 0, 255, 0, 0, 0, 255, 0, 0, 10, 20, 30, 0, 0];
+        let image = decode(&bmp(2, 2, 24, BI_RGB, &[], &pixels)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels, [0, 0, 255, 255, 30, 20, 10, 255, 255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_32_bit_top_down() {
+        let pixels = [0, 255, 0, 128, 255, 0, 0, 255];
+        let image = decode(&bmp(1, -2, 32, BI_RGB, &[], &pixels)).unwrap();
+        assert_eq!(image.pixels, [0, 255, 0, 128, 0, 0, 255, 255]);
+
+        // All-zero fourth bytes are padding, not alpha
+        let image = decode(&bmp(1, -2, 32, BI_RGB, &[], &[0, 255, 0, 0, 255, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [0, 255, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_bitfields() {
+        // 5-6-5, white then pure green
+        let masks: Vec<u8> = [0xF800u32, 0x07E0, 0x001F].iter().flat_map(|m| m.to_le_bytes()).collect();
+        let image = decode(&bmp(2, 1, 16, BI_BITFIELDS, &masks, &[0xFF, 0xFF, 0xE0, 0x07])).unwrap();
+        assert_eq!(image.pixels, [255, 255, 255, 255, 0, 255, 0, 255]);
+
+        // RGBA byte order with an alpha mask, which is kept even when zero
+        let masks: Vec<u8> = [0xFFu32, 0xFF00, 0xFF_0000, 0xFF00_0000].iter().flat_map(|m| m.to_le_bytes()).collect();
+        let image = decode(&bmp(1, 1, 32, BI_ALPHABITFIELDS, &masks, &[10, 20, 30, 0])).unwrap();
+        assert_eq!(image.pixels, [10, 20, 30, 0]);
+    }
+
+    #[test]
+    fn test_palette() {
+        // 1-bit rows are padded to 4 bytes; indices 0, 1, 0 from the high bit
+        let image = decode(&bmp(3, 1, 1, BI_RGB, &PALETTE[..8], &[0b0100_0000, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [0, 0, 255, 255, 0, 255, 0, 255, 0, 0, 255, 255]);
+
+        let image = decode(&bmp(2, 1, 4, BI_RGB, &PALETTE, &[0x21, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 255, 0, 255]);
+
+        assert_eq!(
+            decode(&bmp(1, 1, 8, BI_RGB, &PALETTE, &[3, 0, 0, 0])),
+            Err(DecodeError::InvalidData { message: "palette index 3 is past the 3-color table".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_rle() {
+        let red = [255, 0, 0, 255];
+        let green = [0, 255, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let clear = [0; 4];
+
+        // Bottom row: run of 2 reds, absolute [blue, green, blue] + pad, end of line.
+        // Top row: skip 1 right, one green, end of bitmap.
+        let rle8 = [2, 2, 0, 3, 0, 1, 0, 0, 0, 0, 0, 2, 1, 0, 1, 1, 0, 1];
+        let image = decode(&bmp(5, 2, 8, BI_RLE8, &PALETTE, &rle8)).unwrap();
+        let expected = [clear, green, clear, clear, clear, red, red, blue, green, blue].concat();
+        assert_eq!(image.pixels, expected);
+
+        // Nibbles alternate within a run: red, green, red; then absolute blue, green, red
+        let rle4 = [3, 0x21, 0, 3, 0x01, 0x20, 0, 1];
+        let image = decode(&bmp(6, 1, 4, BI_RLE4, &PALETTE, &rle4)).unwrap();
+        assert_eq!(image.pixels, [red, green, red, blue, green, red].concat());
+
+        assert!(matches!(decode(&bmp(2, 1, 8, BI_RLE8, &PALETTE, &[0, 4, 1])), Err(DecodeError::Truncated { .. })));
+        assert!(matches!(decode(&bmp(2, -1, 8, BI_RLE8, &PALETTE, &[0, 1])), Err(DecodeError::InvalidHeader { .. })));
+
+        // Ending at once is legal: everything stays transparent
+        let image = decode(&bmp(300, 2, 8, BI_RLE8, &PALETTE, &[0, 1])).unwrap();
+        assert!(image.pixels.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            decode(&bmp(2, 2, 24, BI_RGB, &[], &[0; 12])),
+            Err(DecodeError::Truncated { expected: 16, actual: 12 })
+        );
+        assert!(matches!(decode(&bmp(0, 1, 24, BI_RGB, &[], &[])), Err(DecodeError::InvalidHeader { .. })));
+        assert!(matches!(decode(&bmp(1, 1, 24, BI_RLE8, &[], &[0; 4])), Err(DecodeError::InvalidHeader { .. })));
+        assert!(matches!(decode(&bmp(1, 1, 24, 5, &[], &[0; 4])), Err(DecodeError::Unsupported { .. })));
+        assert!(matches!(decode(b"BM\0\0"), Err(DecodeError::Truncated { .. })));
+        assert_eq!(decode(b"P6 1 1 255\n"), Err(DecodeError::UnknownFormat));
+    }
+}
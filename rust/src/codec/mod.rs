@@ -0,0 +1,150 @@
+//! Lightweight image file decoders, so detection can run natively without
+//! a browser canvas or a large image dependency. Every decoder produces
+//! the 8-bit RGBA buffer `detect_chromakey` expects. QOI can also be
+//! written, for fixtures and debug images.
+
+pub mod bmp;
+pub mod netpbm;
+pub mod qoi;
+pub mod tga;
+
+use serde::Serialize;
+
+/// A decoded image: 8-bit RGBA, rows top to bottom, no padding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// An empty image to push `width` x `height` pixels into, refusing sizes
+    /// over `MAX_PIXELS`. Nothing is allocated here: decoders reserve (or
+    /// call `blank`) once they know the file could hold that many pixels,
+    /// except RLE BMP, where a few bytes may legally skip the whole canvas.
+    pub(crate) fn new(width: u32, height: u32) -> Result<Self, DecodeError> {
+        if width == 0 || height == 0 {
+            return Err(DecodeError::InvalidHeader { message: format!("invalid dimensions {}x{}", width, height) });
+        }
+        let pixels = width as u64 * height as u64;
+        if pixels > MAX_PIXELS {
+            return Err(DecodeError::TooLarge { width, height });
+        }
+        Ok(Self { width, height, pixels: Vec::new() })
+    }
+
+    /// `new`, with every pixel transparent black, for decoders that fill
+    /// pixels out of order
+    pub(crate) fn blank(width: u32, height: u32) -> Result<Self, DecodeError> {
+        let mut image = Self::new(width, height)?;
+        image.pixels = vec![0; image.byte_len()];
+        Ok(image)
+    }
+
+    pub(crate) fn set(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&rgba);
+    }
+
+    /// Bytes of RGBA data once every pixel is pushed
+    pub(crate) fn byte_len(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+
+    pub(crate) fn push(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.pixels.extend_from_slice(&[r, g, b, a]);
+    }
+}
+
+// Decoders refuse headers claiming more pixels than this (1 GiB of RGBA),
+// since compressed data can't be checked against the size up front
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// Little-endian integer at `offset`, or a truncation error
+pub(crate) fn u16_le(data: &[u8], offset: usize) -> Result<u16, DecodeError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err(DecodeError::Truncated { expected: offset + 2, actual: data.len() }),
+    }
+}
+
+pub(crate) fn u32_le(data: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(DecodeError::Truncated { expected: offset + 4, actual: data.len() }),
+    }
+}
+
+pub(crate) fn invalid_header(message: String) -> DecodeError {
+    DecodeError::InvalidHeader { message }
+}
+
+/// Why an image file could not be decoded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DecodeError {
+    UnknownFormat,                                  // No decoder recognizes the file's signature
+    InvalidHeader { message: String },
+    Unsupported { feature: String },                // Valid file using a variant we don't decode
+    Truncated { expected: usize, actual: usize },   // Pixel data ends early (bytes or samples)
+    InvalidData { message: String },                // Pixel data that contradicts the header
+    TooLarge { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownFormat => write!(f, "unrecognized image format"),
+            DecodeError::InvalidHeader { message } => write!(f, "invalid image header: {}", message),
+            DecodeError::Unsupported { feature } => write!(f, "unsupported image feature: {}", feature),
+            DecodeError::Truncated { expected, actual } => {
+                write!(f, "image data truncated: expected {}, got {}", expected, actual)
+            }
+            DecodeError::InvalidData { message } => write!(f, "invalid image data: {}", message),
+            DecodeError::TooLarge { width, height } => write!(f, "image too large: {}x{}", width, height),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Why an RGBA buffer could not be encoded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EncodeError {
+    InvalidDimensions { width: u32, height: u32 },
+    BufferTooShort { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::InvalidDimensions { width, height } => write!(f, "invalid image dimensions {}x{}", width, height),
+            EncodeError::BufferTooShort { expected, actual } => {
+                write!(f, "pixel buffer too short: expected {} bytes, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// File extensions of the formats `decode` understands (lowercase)
+pub const FILE_EXTENSIONS: &[&str] = &["ppm", "pgm", "pam", "pnm", "bmp", "dib", "tga", "qoi"];
+
+/// Decode any supported format, picked by the file's leading bytes.
+/// TGA has no signature, so it is tried last.
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if bmp::is_bmp(data) {
+        bmp::decode(data)
+    } else if netpbm::is_netpbm(data) {
+        netpbm::decode(data)
+    } else if qoi::is_qoi(data) {
+        qoi::decode(data)
+    } else if tga::is_tga(data) {
+        tga::decode(data)
+    } else {
+        Err(DecodeError::UnknownFormat)
+    }
+}
@@ -0,0 +1,374 @@
+//! Netpbm images: P2/P5 graymaps and P3/P6 pixmaps (ASCII/binary), and P7
+//! PAM with optional alpha. Samples up to 16 bits are scaled to 8 bits.
+
+use super::{DecodeError, Image, invalid_header};
+
+/// Channel layout of one pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channels {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    RgbAlpha,
+}
+
+impl Channels {
+    fn depth(self) -> usize {
+        match self {
+            Channels::Gray => 1,
+            Channels::GrayAlpha => 2,
+            Channels::Rgb => 3,
+            Channels::RgbAlpha => 4,
+        }
+    }
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    maxval: u32,
+    channels: Channels,
+    ascii: bool,
+}
+
+const MAX_MAXVAL: u32 = 65535;
+
+/// True if `data` starts with a Netpbm magic number (P1 - P7)
+pub fn is_netpbm(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == b'P' && (b'1'..=b'7').contains(&data[1])
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !is_netpbm(data) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let mut cursor = Cursor { data, pos: 2 };
+    let header = match data[1] {
+        b'2' => cursor.pnm_header(Channels::Gray, true)?,
+        b'3' => cursor.pnm_header(Channels::Rgb, true)?,
+        b'5' => cursor.pnm_header(Channels::Gray, false)?,
+        b'6' => cursor.pnm_header(Channels::Rgb, false)?,
+        b'7' => cursor.pam_header()?,
+        _ => return Err(DecodeError::Unsupported { feature: "PBM bitmaps (P1/P4)".to_string() }),
+    };
+
+    let mut image = Image::new(header.width, header.height)?;
+    let pixel_count = header.width as usize * header.height as usize;
+    let depth = header.channels.depth();
+    let samples = pixel_count * depth;
+
+    let mut tuple = [0u8; 4];
+    if header.ascii {
+        for i in 0..samples {
+            tuple[i % depth] = scale(cursor.ascii_sample(i, samples)?, header.maxval)?;
+            if i % depth == depth - 1 {
+                push_tuple(&mut image, &tuple, header.channels);
+            }
+        }
+    } else {
+        let bytes_per_sample = if header.maxval > 255 { 2 } else { 1 };
+        let raster = &data[cursor.pos..];
+        let expected = samples * bytes_per_sample;
+        if raster.len() < expected {
+            return Err(DecodeError::Truncated { expected, actual: raster.len() });
+        }
+        image.pixels.reserve_exact(image.byte_len());
+
+        for pixel in raster[..expected].chunks_exact(depth * bytes_per_sample) {
+            for (value, sample) in tuple.iter_mut().zip(pixel.chunks_exact(bytes_per_sample)) {
+                // 16-bit samples are big-endian
+                let raw = sample.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+                *value = scale(raw, header.maxval)?;
+            }
+            push_tuple(&mut image, &tuple, header.channels);
+        }
+    }
+
+    Ok(image)
+}
+
+/// Rescale a sample from 0 - maxval to 0 - 255, rounding to nearest
+fn scale(value: u32, maxval: u32) -> Result<u8, DecodeError> {
+    if value > maxval {
+        return Err(DecodeError::InvalidData { message: format!("sample {} exceeds maxval {}", value, maxval) });
+    }
+    if maxval == 255 {
+        return Ok(value as u8);
+    }
+    Ok(((value * 255 + maxval / 2) / maxval) as u8)
+}
+
+fn push_tuple(image: &mut Image, tuple: &[u8; 4], channels: Channels) {
+    match channels {
+        Channels::Gray => image.push(tuple[0], tuple[0], tuple[0], 255),
+        Channels::GrayAlpha => image.push(tuple[0], tuple[0], tuple[0], tuple[1]),
+        Channels::Rgb => image.push(tuple[0], tuple[1], tuple[2], 255),
+        Channels::RgbAlpha => image.push(tuple[0], tuple[1], tuple[2], tuple[3]),
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    /// Skip whitespace and `#` comments (which run to the end of the line)
+    fn skip_space(&mut self) {
+        while let Some(byte) = self.peek() {
+            if byte == b'#' {
+                while self.peek().is_some_and(|b| b != b'\n' && b != b'\r') {
+                    self.pos += 1;
+                }
+            } else if byte.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Digits at the cursor, if any, and the value they spell (None on overflow)
+    fn digits(&mut self) -> Option<(usize, Option<u32>)> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits = &self.data[start..self.pos];
+        if digits.is_empty() {
+            return None;
+        }
+        let value = digits.iter().try_fold(0u32, |acc, &d| acc.checked_mul(10)?.checked_add((d - b'0') as u32));
+        Some((digits.len(), value))
+    }
+
+    /// Next header number, which must be followed by whitespace or a comment
+    fn header_number(&mut self, what: &str) -> Result<u32, DecodeError> {
+        self.skip_space();
+        let found = self.describe_next();
+        let (_, value) = self.digits()
+            .ok_or_else(|| invalid_header(format!("expected {}, found {}", what, found)))?;
+        let value = value.ok_or_else(|| invalid_header(format!("{} is too large", what)))?;
+        if self.peek().is_some_and(|b| !b.is_ascii_whitespace() && b != b'#') {
+            return Err(invalid_header(format!("expected whitespace after {}, found {}", what, self.describe_next())));
+        }
+        Ok(value)
+    }
+
+    fn describe_next(&self) -> String {
+        match self.peek() {
+            Some(byte) if byte.is_ascii_graphic() => format!("'{}'", byte as char),
+            Some(byte) => format!("byte 0x{:02x}", byte),
+            None => "end of file".to_string(),
+        }
+    }
+
+    /// Header of P2/P3/P5/P6: width, height, maxval
+    fn pnm_header(&mut self, channels: Channels, ascii: bool) -> Result<Header, DecodeError> {
+        if !self.peek().is_some_and(|b| b.is_ascii_whitespace() || b == b'#') {
+            return Err(invalid_header(format!("expected whitespace after magic number, found {}", self.describe_next())));
+        }
+        let width = self.header_number("width")?;
+        let height = self.header_number("height")?;
+        let maxval = self.header_number("maxval")?;
+        check_maxval(maxval)?;
+
+        if !ascii {
+            // Exactly one whitespace byte separates the header from binary pixels
+            if self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+                self.pos += 1;
+            } else {
+                return Err(invalid_header(format!("expected whitespace after maxval, found {}", self.describe_next())));
+            }
+        }
+        Ok(Header { width, height, maxval, channels, ascii })
+    }
+
+    /// Header of P7: `KEY value` lines up to ENDHDR
+    fn pam_header(&mut self) -> Result<Header, DecodeError> {
+        let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+        let mut tuple_type: Option<String> = None;
+
+        loop {
+            let end = self.data[self.pos..].iter().position(|&b| b == b'\n')
+                .ok_or_else(|| invalid_header("PAM header has no ENDHDR".to_string()))?;
+            let line = String::from_utf8_lossy(&self.data[self.pos..self.pos + end]).into_owned();
+            self.pos += end + 1;
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let number = || value.parse::<u32>()
+                .map_err(|_| invalid_header(format!("{} must be a number, found '{}'", key, value)));
+            match key {
+                "WIDTH" => width = Some(number()?),
+                "HEIGHT" => height = Some(number()?),
+                "DEPTH" => depth = Some(number()?),
+                "MAXVAL" => maxval = Some(number()?),
+                // Repeated TUPLTYPE lines concatenate
+                "TUPLTYPE" => match &mut tuple_type {
+                    Some(existing) => {
+                        existing.push(' ');
+                        existing.push_str(value);
+                    }
+                    None => tuple_type = Some(value.to_string()),
+                },
+                "ENDHDR" => break,
+                _ => return Err(invalid_header(format!("unknown PAM header field '{}'", key))),
+            }
+        }
+
+        let missing = |field: &str| invalid_header(format!("PAM header is missing {}", field));
+        let width = width.ok_or_else(|| missing("WIDTH"))?;
+        let height = height.ok_or_else(|| missing("HEIGHT"))?;
+        let depth = depth.ok_or_else(|| missing("DEPTH"))?;
+        let maxval = maxval.ok_or_else(|| missing("MAXVAL"))?;
+        check_maxval(maxval)?;
+
+        let channels = match tuple_type.as_deref() {
+            Some("BLACKANDWHITE" | "GRAYSCALE") => Channels::Gray,
+            Some("BLACKANDWHITE_ALPHA" | "GRAYSCALE_ALPHA") => Channels::GrayAlpha,
+            Some("RGB") => Channels::Rgb,
+            Some("RGB_ALPHA") => Channels::RgbAlpha,
+            Some(other) => return Err(DecodeError::Unsupported { feature: format!("PAM tuple type {}", other) }),
+            None => match depth {
+                1 => Channels::Gray,
+                2 => Channels::GrayAlpha,
+                3 => Channels::Rgb,
+                4 => Channels::RgbAlpha,
+                _ => return Err(DecodeError::Unsupported { feature: format!("PAM depth {}", depth) }),
+            },
+        };
+        if channels.depth() != depth as usize {
+            return Err(invalid_header(format!(
+                "TUPLTYPE {} needs DEPTH {}, found {}",
+                tuple_type.unwrap_or_default(),
+                channels.depth(),
+                depth
+            )));
+        }
+
+        Ok(Header { width, height, maxval, channels, ascii: false })
+    }
+
+    /// Next ASCII raster sample; `index` of `total` is for the error message
+    fn ascii_sample(&mut self, index: usize, total: usize) -> Result<u32, DecodeError> {
+        self.skip_space();
+        if self.peek().is_none() {
+            return Err(DecodeError::Truncated { expected: total, actual: index });
+        }
+        let found = self.describe_next();
+        match self.digits() {
+            Some((_, Some(value))) => Ok(value),
+            Some((_, None)) => Err(DecodeError::InvalidData { message: format!("sample {} is too large", index) }),
+            None => Err(DecodeError::InvalidData { message: format!("expected a sample, found {}", found) }),
+        }
+    }
+}
+
+fn check_maxval(maxval: u32) -> Result<(), DecodeError> {
+    if (1..=MAX_MAXVAL).contains(&maxval) {
+        Ok(())
+    } else {
+        Err(invalid_header(format!("maxval {} is outside 1 - {}", maxval, MAX_MAXVAL)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_error(data: &[u8]) -> String {
+        match decode(data) {
+            Err(DecodeError::InvalidHeader { message }) => message,
+            other => panic!("expected a header error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_binary_ppm() {
+        let mut data = b"P6\n# green screen\n2 1\n255\n".to_vec();
+        data.extend_from_slice(&[0, 255, 0, 10, 20, 30]);
+
+        let image = decode(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [0, 255, 0, 255, 10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_ascii_formats() {
+        let ppm = decode(b"P3 2 1 15 # comment\n 0 15 0\n15 0 0").unwrap();
+        assert_eq!(ppm.pixels, [0, 255, 0, 255, 255, 0, 0, 255]);
+
+        let pgm = decode(b"P2\n3 1\n2\n0 1 2\n").unwrap();
+        assert_eq!(pgm.pixels, [0, 0, 0, 255, 128, 128, 128, 255, 255, 255, 255, 255]);
+
+        assert_eq!(decode(b"P3 1 1 255 0 255"), Err(DecodeError::Truncated { expected: 3, actual: 2 }));
+        assert!(matches!(decode(b"P3 1 1 255 0 256 0"), Err(DecodeError::InvalidData { .. })));
+        assert!(matches!(decode(b"P2 1 1 255 x"), Err(DecodeError::InvalidData { .. })));
+    }
+
+    #[test]
+    fn test_16_bit_samples() {
+        let mut data = b"P5 2 1 65535\n".to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0x80, 0x00]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, [255, 255, 255, 255, 128, 128, 128, 255]);
+
+        // 10-bit samples, two bytes each
+        let mut data = b"P6 1 1 1023\n".to_vec();
+        data.extend_from_slice(&[0x03, 0xFF, 0x00, 0x00, 0x02, 0x00]);
+        assert_eq!(decode(&data).unwrap().pixels, [255, 0, 128, 255]);
+
+        let mut data = b"P5 1 1 1023\n".to_vec();
+        data.extend_from_slice(&[0x04, 0x00]);
+        assert!(matches!(decode(&data), Err(DecodeError::InvalidData { .. })));
+    }
+
+    #[test]
+    fn test_pam() {
+        let mut data = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n".to_vec();
+        data.extend_from_slice(&[0, 255, 0, 255, 0, 255, 0, 0]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, [0, 255, 0, 255, 0, 255, 0, 0]);
+
+        // Gray + alpha, type inferred from depth, 16-bit
+        let mut data = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 65535\nENDHDR\n".to_vec();
+        data.extend_from_slice(&[0x80, 0x00, 0xFF, 0xFF]);
+        assert_eq!(decode(&data).unwrap().pixels, [128, 128, 128, 255]);
+
+        let mismatch = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB\nENDHDR\n";
+        assert_eq!(header_error(mismatch), "TUPLTYPE RGB needs DEPTH 3, found 4");
+        assert_eq!(header_error(b"P7\nWIDTH 1\nHEIGHT 1\nMAXVAL 255\nENDHDR\n"), "PAM header is missing DEPTH");
+        assert_eq!(header_error(b"P7\nWIDTH 1\n"), "PAM header has no ENDHDR");
+        assert_eq!(header_error(b"P7\nWIDTH one\nENDHDR\n"), "WIDTH must be a number, found 'one'");
+        assert!(matches!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\nTUPLTYPE CMYK\nENDHDR\n"),
+            Err(DecodeError::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        assert_eq!(header_error(b"P6 2"), "expected height, found end of file");
+        assert_eq!(header_error(b"P6 2 x 255\n"), "expected height, found 'x'");
+        assert_eq!(header_error(b"P6 2 2 0\n"), "maxval 0 is outside 1 - 65535");
+        assert_eq!(header_error(b"P6 2 2 70000\n"), "maxval 70000 is outside 1 - 65535");
+        assert_eq!(header_error(b"P6 2a 2 255\n"), "expected whitespace after width, found 'a'");
+        assert_eq!(header_error(b"P6 99999999999 1 255\n"), "width is too large");
+        assert_eq!(header_error(b"P62 2 255\n"), "expected whitespace after magic number, found '2'");
+        assert_eq!(header_error(b"P6 0 2 255\n"), "invalid dimensions 0x2");
+
+        assert_eq!(decode(b"P6 2 1 255\n\x00\x01"), Err(DecodeError::Truncated { expected: 6, actual: 2 }));
+        assert!(matches!(decode(b"P4 1 1\n\x00"), Err(DecodeError::Unsupported { .. })));
+        assert_eq!(decode(b"GIF89a"), Err(DecodeError::UnknownFormat));
+    }
+}
@@ -0,0 +1,286 @@
+//! QOI ("Quite OK Image" format): a small lossless RGBA format, used for
+//! test fixtures and debug output such as matte previews. Both directions
+//! are supported; see https://qoiformat.org/qoi-specification.pdf.
+
+use super::{DecodeError, EncodeError, Image, invalid_header};
+
+const MAGIC: &[u8] = b"qoif";
+const HEADER_LEN: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+// Chunk tags: 2-bit tags in the high bits, except the two 8-bit ones
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const TAG_MASK: u8 = 0xC0;
+
+const MAX_RUN: u8 = 62;
+
+// Both sides start from opaque black with an empty index
+const START_PIXEL: [u8; 4] = [0, 0, 0, 255];
+
+/// Slot of `pixel` in the 64-entry table of recently seen pixels
+fn index_position(pixel: [u8; 4]) -> usize {
+    let [r, g, b, a] = pixel.map(|c| c as usize);
+    (r * 3 + g * 5 + b * 7 + a * 11) % 64
+}
+
+/// True if `data` starts with the QOI signature
+pub fn is_qoi(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encode an RGBA buffer. The header says 3 channels if every pixel is
+/// opaque, 4 otherwise; the color space is always sRGB.
+pub fn encode(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, EncodeError> {
+    if width == 0 || height == 0 {
+        return Err(EncodeError::InvalidDimensions { width, height });
+    }
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(4))
+        .ok_or(EncodeError::InvalidDimensions { width, height })?;
+    if pixels.len() < expected {
+        return Err(EncodeError::BufferTooShort { expected, actual: pixels.len() });
+    }
+    let pixels = &pixels[..expected];
+    let opaque = pixels.chunks_exact(4).all(|p| p[3] == 255);
+
+    // Worst case is an RGBA chunk per pixel
+    let mut out = Vec::with_capacity(HEADER_LEN + expected + expected / 4 + END_MARKER.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&width.to_be_bytes());
+    out.extend_from_slice(&height.to_be_bytes());
+    out.extend_from_slice(&[if opaque { 3 } else { 4 }, 0]);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut previous = START_PIXEL;
+    let mut run = 0u8;
+    let pixel_count = expected / 4;
+
+    for (i, chunk) in pixels.chunks_exact(4).enumerate() {
+        let pixel = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        if pixel == previous {
+            run += 1;
+            if run == MAX_RUN || i == pixel_count - 1 {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let position = index_position(pixel);
+        if index[position] == pixel {
+            out.push(OP_INDEX | position as u8);
+        } else {
+            index[position] = pixel;
+            if pixel[3] == previous[3] {
+                // Channel differences wrap around, so 255 -> 0 is +1
+                let dr = pixel[0].wrapping_sub(previous[0]) as i8;
+                let dg = pixel[1].wrapping_sub(previous[1]) as i8;
+                let db = pixel[2].wrapping_sub(previous[2]) as i8;
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+
+                if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+                    out.push(OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+                } else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+                    out.push(OP_LUMA | (dg + 32) as u8);
+                    out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    out.extend_from_slice(&[OP_RGB, pixel[0], pixel[1], pixel[2]]);
+                }
+            } else {
+                out.extend_from_slice(&[OP_RGBA, pixel[0], pixel[1], pixel[2], pixel[3]]);
+            }
+        }
+        previous = pixel;
+    }
+
+    out.extend_from_slice(&END_MARKER);
+    Ok(out)
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !is_qoi(data) {
+        return Err(DecodeError::UnknownFormat);
+    }
+    if data.len() < HEADER_LEN {
+        return Err(DecodeError::Truncated { expected: HEADER_LEN, actual: data.len() });
+    }
+
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+    let (channels, colorspace) = (data[12], data[13]);
+    if channels != 3 && channels != 4 {
+        return Err(invalid_header(format!("QOI channels must be 3 or 4, found {}", channels)));
+    }
+    if colorspace > 1 {
+        return Err(invalid_header(format!("QOI color space must be 0 or 1, found {}", colorspace)));
+    }
+
+    let mut image = Image::new(width, height)?;
+    let pixel_count = width as usize * height as usize;
+    // A run chunk covers at most 62 pixels, which bounds how many pixels
+    // the data can hold before anything is allocated
+    let chunks = &data[HEADER_LEN..];
+    if chunks.len() * (MAX_RUN as usize) < pixel_count {
+        return Err(DecodeError::Truncated { expected: pixel_count, actual: chunks.len() * MAX_RUN as usize });
+    }
+    image.pixels.reserve_exact(image.byte_len());
+
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = START_PIXEL;
+    let mut decoded = 0;
+    let mut pos = 0;
+    let truncated = |decoded| DecodeError::Truncated { expected: pixel_count, actual: decoded };
+    let operand = |pos: usize, len: usize, decoded| chunks.get(pos..pos + len).ok_or_else(|| truncated(decoded));
+
+    while decoded < pixel_count {
+        let tag = operand(pos, 1, decoded)?[0];
+        pos += 1;
+        let mut repeat = 1;
+        match tag {
+            OP_RGB => {
+                let rgb = operand(pos, 3, decoded)?;
+                pixel = [rgb[0], rgb[1], rgb[2], pixel[3]];
+                pos += 3;
+            }
+            OP_RGBA => {
+                let rgba = operand(pos, 4, decoded)?;
+                pixel = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                pos += 4;
+            }
+            _ => match tag & TAG_MASK {
+                OP_INDEX => pixel = index[tag as usize],
+                OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add((tag >> 4) & 0x03).wrapping_sub(2);
+                    pixel[1] = pixel[1].wrapping_add((tag >> 2) & 0x03).wrapping_sub(2);
+                    pixel[2] = pixel[2].wrapping_add(tag & 0x03).wrapping_sub(2);
+                }
+                OP_LUMA => {
+                    let second = operand(pos, 1, decoded)?[0];
+                    pos += 1;
+                    let dg = (tag & 0x3F).wrapping_sub(32);
+                    pixel[0] = pixel[0].wrapping_add(dg).wrapping_add(second >> 4).wrapping_sub(8);
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(dg).wrapping_add(second & 0x0F).wrapping_sub(8);
+                }
+                // OP_RUN; runs past the last pixel are clipped
+                _ => repeat = ((tag & 0x3F) as usize + 1).min(pixel_count - decoded),
+            },
+        }
+
+        index[index_position(pixel)] = pixel;
+        for _ in 0..repeat {
+            image.push(pixel[0], pixel[1], pixel[2], pixel[3]);
+        }
+        decoded += repeat;
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let encoded = encode(pixels, width, height).unwrap();
+        let image = decode(&encoded).unwrap();
+        assert_eq!((image.width, image.height), (width, height));
+        assert_eq!(image.pixels, pixels);
+        encoded
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Gradients (diff and luma chunks), noise (full RGB/RGBA), repeats
+        // (runs and index hits) and varying alpha
+        let (width, height) = (37u32, 23u32);
+        let mut seed = 12345u32;
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let noise = (seed >> 16) as u8;
+                let pixel = match (x / 8) % 4 {
+                    0 => [x as u8 * 3, y as u8 * 2, 128, 255],
+                    1 => [noise, noise.wrapping_mul(7), noise ^ 0x5A, 255],
+                    2 => [0, 255, 0, 255],
+                    _ => [noise % 4, 200, 10, if noise.is_multiple_of(3) { 255 } else { noise }],
+                };
+                pixels.extend_from_slice(&pixel);
+            }
+        }
+        let encoded = round_trip(&pixels, width, height);
+        assert_eq!(encoded[12], 4);
+        assert!(encoded.ends_with(&END_MARKER));
+
+        // Long runs are split every 62 pixels; an opaque image says 3 channels
+        let green = [0, 255, 0, 255].repeat(200);
+        let encoded = round_trip(&green, 20, 10);
+        assert_eq!(encoded[12], 3);
+        assert!(encoded.len() < 40, "{} bytes", encoded.len());
+
+        round_trip(&[0, 0, 0, 255], 1, 1);
+        round_trip(&[255, 255, 255, 0, 0, 0, 0, 0, 255, 255, 255, 0], 3, 1);
+    }
+
+    #[test]
+    fn test_chunk_encoding() {
+        // Opaque black (a run from the start pixel), +1 red (diff), +20
+        // green with red/blue following (luma), a new color (RGB), the
+        // second pixel again (index), then half-transparent (RGBA)
+        let pixels = [
+            0, 0, 0, 255,
+            1, 0, 0, 255,
+            21, 20, 20, 255,
+            200, 100, 50, 255,
+            1, 0, 0, 255,
+            1, 0, 0, 128,
+        ];
+        let encoded = encode(&pixels, 6, 1).unwrap();
+        let slot = index_position([1, 0, 0, 255]) as u8;
+        assert_eq!(
+            encoded[HEADER_LEN..encoded.len() - END_MARKER.len()],
+            [
+                OP_RUN,
+                OP_DIFF | 3 << 4 | 2 << 2 | 2,
+                OP_LUMA | 52, 8 << 4 | 8,
+                OP_RGB, 200, 100, 50,
+                OP_INDEX | slot,
+                OP_RGBA, 1, 0, 0, 128,
+            ]
+        );
+        assert_eq!(decode(&encoded).unwrap().pixels, pixels);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(encode(&[0; 4], 0, 1), Err(EncodeError::InvalidDimensions { width: 0, height: 1 }));
+        assert_eq!(encode(&[0; 7], 2, 1), Err(EncodeError::BufferTooShort { expected: 8, actual: 7 }));
+
+        let encoded = encode(&[10, 20, 30, 255, 40, 50, 60, 255], 2, 1).unwrap();
+        assert_eq!(
+            decode(&encoded[..HEADER_LEN + 4]),
+            Err(DecodeError::Truncated { expected: 2, actual: 1 })
+        );
+        assert!(matches!(decode(&encoded[..10]), Err(DecodeError::Truncated { .. })));
+
+        let mut bad_channels = encoded.clone();
+        bad_channels[12] = 2;
+        assert!(matches!(decode(&bad_channels), Err(DecodeError::InvalidHeader { .. })));
+
+        // A tiny file can't claim a huge image
+        let mut huge = encoded.clone();
+        huge[4..12].copy_from_slice(&[0, 0, 0x40, 0, 0, 0, 0x40, 0]);
+        assert!(matches!(decode(&huge), Err(DecodeError::Truncated { .. })));
+        assert_eq!(decode(b"GIF89a"), Err(DecodeError::UnknownFormat));
+    }
+}
@@ -0,0 +1,212 @@
+//! Truevision TGA: uncompressed and RLE true-color (24/32-bit) and
+//! grayscale (8-bit, or 16-bit with alpha) images, stored from any of the
+//! four corners.
+
+use super::{DecodeError, Image, invalid_header, u16_le};
+
+const HEADER_LEN: usize = 18;
+// TGA 2.0 files end with this signature
+const FOOTER_SIGNATURE: &[u8] = b"TRUEVISION-XFILE.\0";
+
+// Image types; RLE_FLAG is added for run-length encoded data
+const COLOR_MAPPED: u8 = 1;
+const TRUE_COLOR: u8 = 2;
+const GRAYSCALE: u8 = 3;
+const RLE_FLAG: u8 = 8;
+
+// Image descriptor bits
+const ALPHA_BITS_MASK: u8 = 0x0F;
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+/// TGA has no leading signature, so this accepts files carrying the TGA 2.0
+/// footer or whose header is plausible
+pub fn is_tga(data: &[u8]) -> bool {
+    if data.len() < HEADER_LEN {
+        return false;
+    }
+    if data.ends_with(FOOTER_SIGNATURE) {
+        return true;
+    }
+    let width = u16::from_le_bytes([data[12], data[13]]);
+    let height = u16::from_le_bytes([data[14], data[15]]);
+    data[1] <= 1
+        && matches!(data[2] & !RLE_FLAG, COLOR_MAPPED | TRUE_COLOR | GRAYSCALE)
+        && matches!(data[16], 8 | 15 | 16 | 24 | 32)
+        && width > 0
+        && height > 0
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !is_tga(data) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let id_len = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2];
+    let map_len = u16_le(data, 5)? as usize;
+    let map_entry_bits = data[7] as usize;
+    let width = u16_le(data, 12)? as u32;
+    let height = u16_le(data, 14)? as u32;
+    let depth = data[16];
+    let descriptor = data[17];
+
+    let gray = match image_type & !RLE_FLAG {
+        TRUE_COLOR => false,
+        GRAYSCALE => true,
+        COLOR_MAPPED => return Err(DecodeError::Unsupported { feature: "color-mapped TGA".to_string() }),
+        _ => return Err(invalid_header(format!("unknown TGA image type {}", image_type))),
+    };
+    let bytes_per_pixel = match (gray, depth) {
+        (false, 24) => 3,
+        (false, 32) => 4,
+        (true, 8) => 1,
+        (true, 16) => 2,
+        (false, 15 | 16) => return Err(DecodeError::Unsupported { feature: format!("{}-bit TGA", depth) }),
+        _ => return Err(invalid_header(format!("{}-bit {} TGA", depth, if gray { "grayscale" } else { "true-color" }))),
+    };
+    // 32-bit color and 16-bit gray only carry alpha if the descriptor says so
+    let has_alpha = bytes_per_pixel % 2 == 0 && descriptor & ALPHA_BITS_MASK != 0;
+
+    // True-color files may still carry a color map, which is skipped
+    let map_bytes = if color_map_type == 1 { map_len * map_entry_bits.div_ceil(8) } else { 0 };
+    let mut pos = HEADER_LEN + id_len + map_bytes;
+
+    let pixel_count = width as usize * height as usize;
+    let expected = if image_type & RLE_FLAG == 0 {
+        pos + pixel_count * bytes_per_pixel
+    } else {
+        // A packet covers at most 128 pixels, so a short file can't claim a
+        // huge canvas before anything is allocated
+        pos + pixel_count.div_ceil(128) * (1 + bytes_per_pixel)
+    };
+    if data.len() < expected {
+        return Err(DecodeError::Truncated { expected, actual: data.len() });
+    }
+    let mut image = Image::blank(width, height)?;
+
+    // Pixels are stored row by row from the corner the descriptor names
+    let mut place = |i: usize, pixel: &[u8]| {
+        let (row, col) = ((i / width as usize) as u32, (i % width as usize) as u32);
+        let x = if descriptor & RIGHT_TO_LEFT != 0 { width - 1 - col } else { col };
+        let y = if descriptor & TOP_TO_BOTTOM != 0 { row } else { height - 1 - row };
+        image.set(x, y, to_rgba(pixel, has_alpha));
+    };
+
+    if image_type & RLE_FLAG == 0 {
+        for (i, pixel) in data[pos..].chunks_exact(bytes_per_pixel).take(pixel_count).enumerate() {
+            place(i, pixel);
+        }
+    } else {
+        // Packets: a header byte whose high bit marks a run of one repeated
+        // pixel rather than literal pixels, and whose low 7 bits are count - 1.
+        // Packets may span rows.
+        let mut i = 0;
+        let truncated = |i| DecodeError::Truncated { expected: pixel_count, actual: i };
+        while i < pixel_count {
+            let packet = *data.get(pos).ok_or_else(|| truncated(i))?;
+            let len = ((packet & 0x7F) as usize + 1).min(pixel_count - i);
+            let repeated = packet & 0x80 != 0;
+            let stored = if repeated { 1 } else { len };
+            let pixels = data.get(pos + 1..pos + 1 + stored * bytes_per_pixel).ok_or_else(|| truncated(i))?;
+            pos += 1 + pixels.len();
+
+            for n in 0..len {
+                let pixel = if repeated { pixels } else { &pixels[n * bytes_per_pixel..(n + 1) * bytes_per_pixel] };
+                place(i + n, pixel);
+            }
+            i += len;
+        }
+    }
+    Ok(image)
+}
+
+/// One stored pixel (gray, gray + alpha, BGR or BGRA) as RGBA
+fn to_rgba(pixel: &[u8], has_alpha: bool) -> [u8; 4] {
+    let alpha = |a: u8| if has_alpha { a } else { 255 };
+    match *pixel {
+        [gray] => [gray, gray, gray, 255],
+        [gray, a] => [gray, gray, gray, alpha(a)],
+        [b, g, r] => [r, g, b, 255],
+        [b, g, r, a, ..] => [r, g, b, alpha(a)],
+        [] => unreachable!("TGA pixels are 1 - 4 bytes"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A TGA with a two-byte image ID, which decoding has to skip
+    fn tga(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8, pixels: &[u8]) -> Vec<u8> {
+        let mut data = vec![2, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[depth, descriptor]);
+        data.extend_from_slice(b"id");
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    #[test]
+    fn test_uncompressed_bottom_up() {
+        // BGR, bottom row first
+        let pixels = [0, 0, 255, 0, 255, 0, 255, 0, 0, 10, 20, 30];
+        let image = decode(&tga(TRUE_COLOR, 2, 2, 24, 0, &pixels)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels, [0, 0, 255, 255, 30, 20, 10, 255, 255, 0, 0, 255, 0, 255, 0, 255]);
+
+        // Without alpha bits in the descriptor the fourth byte is ignored
+        let image = decode(&tga(TRUE_COLOR, 1, 1, 32, 0, &[0, 255, 0, 7])).unwrap();
+        assert_eq!(image.pixels, [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_rle_top_down() {
+        // A run of 3 semi-transparent greens spanning both rows, then a
+        // literal packet of red; rows run right to left
+        let pixels = [0x82, 0, 255, 0, 128, 0x00, 0, 0, 255, 255];
+        let image = decode(&tga(TRUE_COLOR | RLE_FLAG, 2, 2, 32, TOP_TO_BOTTOM | RIGHT_TO_LEFT | 8, &pixels)).unwrap();
+        let green = [0, 255, 0, 128];
+        let red = [255, 0, 0, 255];
+        assert_eq!(image.pixels, [green, green, red, green].concat());
+
+        // Runs longer than the image are clipped
+        let image = decode(&tga(GRAYSCALE | RLE_FLAG, 2, 1, 8, TOP_TO_BOTTOM, &[0xFF, 200])).unwrap();
+        assert_eq!(image.pixels, [200, 200, 200, 255, 200, 200, 200, 255]);
+    }
+
+    #[test]
+    fn test_grayscale() {
+        let image = decode(&tga(GRAYSCALE, 2, 1, 8, 0, &[0, 255])).unwrap();
+        assert_eq!(image.pixels, [0, 0, 0, 255, 255, 255, 255, 255]);
+
+        let image = decode(&tga(GRAYSCALE, 1, 1, 16, 8, &[100, 50])).unwrap();
+        assert_eq!(image.pixels, [100, 100, 100, 50]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            decode(&tga(TRUE_COLOR, 2, 1, 24, 0, &[0; 4])),
+            Err(DecodeError::Truncated { expected: 26, actual: 24 })
+        );
+        assert_eq!(
+            decode(&tga(TRUE_COLOR | RLE_FLAG, 4, 1, 24, 0, &[0x81, 1, 2, 3, 0x01, 1, 2, 3])),
+            Err(DecodeError::Truncated { expected: 4, actual: 2 })
+        );
+        // One run packet can't fill 16384 x 16384 pixels; refused before allocating 1 GiB
+        assert_eq!(
+            decode(&tga(TRUE_COLOR | RLE_FLAG, 16384, 16384, 24, 0, &[0xFF, 0, 255, 0])),
+            Err(DecodeError::Truncated { expected: 20 + (1 << 21) * 4, actual: 24 })
+        );
+        assert!(matches!(decode(&tga(COLOR_MAPPED, 1, 1, 8, 0, &[0])), Err(DecodeError::Unsupported { .. })));
+        assert!(matches!(decode(&tga(TRUE_COLOR, 1, 1, 16, 0, &[0, 0])), Err(DecodeError::Unsupported { .. })));
+        assert!(matches!(decode(&tga(GRAYSCALE, 1, 1, 24, 0, &[0; 3])), Err(DecodeError::InvalidHeader { .. })));
+
+        assert!(!is_tga(&tga(TRUE_COLOR, 0, 1, 24, 0, &[])));
+        assert!(!is_tga(b"P6 1 1 255\n0123456789"));
+        assert_eq!(decode(b"not an image at all"), Err(DecodeError::UnknownFormat));
+    }
+}
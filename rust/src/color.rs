@@ -7,13 +7,63 @@ pub struct RGB {
     pub b: u8,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct HSV {
     pub h: f32,
     pub s: f32,
     pub v: f32,
 }
 
+/// sRGB with the transfer curve removed, channels 0.0 - 1.0
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LinearRGB {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+/// CIELAB (D65 white point), L 0 - 100
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// OKLab, L 0.0 - 1.0
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct OKLab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// Full-range YCbCr on a 0 - 255 scale (chroma centered on 128)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct YCbCr {
+    pub y: f32,
+    pub cb: f32,
+    pub cr: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YCbCrMatrix {
+    Bt601,          // SD video, JPEG
+    #[default]
+    Bt709,          // HD video
+}
+
+/// Color space used for clustering distances and histogram hue binning
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    #[default]
+    Hsv,
+    Lab,            // CIELAB with Delta-E 2000 distance
+    Oklab,          // OKLab with Euclidean distance
+}
+
 impl RGB {
     #[inline]
     pub fn to_hsv(&self) -> HSV {
@@ -79,10 +129,347 @@ impl HSV {
         }
     }
 
-    pub fn is_chromakey_candidate(&self) -> bool {
-        // Much more lenient: Accept any pixel with some color (not pure gray)
+    pub fn is_chromakey_candidate(&self, min_saturation: f32) -> bool {
+        // Lenient by default (min_saturation 0.3): accept any pixel with some color
         // This allows darker/less saturated chromakeys and lighting variations
-        self.s > 0.3 && self.v > 0.1
+        self.s > min_saturation && self.v > 0.1
+    }
+}
+
+impl RGB {
+    pub fn to_linear(&self) -> LinearRGB {
+        LinearRGB {
+            r: srgb_to_linear(self.r as f32 / 255.0),
+            g: srgb_to_linear(self.g as f32 / 255.0),
+            b: srgb_to_linear(self.b as f32 / 255.0),
+        }
+    }
+
+    pub fn to_lab(&self) -> Lab {
+        self.to_linear().to_lab()
+    }
+
+    pub fn to_oklab(&self) -> OKLab {
+        self.to_linear().to_oklab()
+    }
+
+    pub fn to_ycbcr(&self, matrix: YCbCrMatrix) -> YCbCr {
+        let (kr, kb) = matrix.coefficients();
+        let r = self.r as f32;
+        let g = self.g as f32;
+        let b = self.b as f32;
+
+        let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+        YCbCr {
+            y,
+            cb: 128.0 + (b - y) / (2.0 * (1.0 - kb)),
+            cr: 128.0 + (r - y) / (2.0 * (1.0 - kr)),
+        }
+    }
+}
+
+impl LinearRGB {
+    pub fn to_rgb(&self) -> RGB {
+        RGB {
+            r: to_u8(linear_to_srgb(self.r)),
+            g: to_u8(linear_to_srgb(self.g)),
+            b: to_u8(linear_to_srgb(self.b)),
+        }
+    }
+
+    pub fn to_lab(&self) -> Lab {
+        // Linear sRGB -> XYZ, normalized by the D65 white point
+        let x = (0.4124564 * self.r + 0.3575761 * self.g + 0.1804375 * self.b) / D65_X;
+        let y = 0.2126729 * self.r + 0.7151522 * self.g + 0.0721750 * self.b;
+        let z = (0.0193339 * self.r + 0.119192 * self.g + 0.9503041 * self.b) / D65_Z;
+
+        let fx = lab_f(x);
+        let fy = lab_f(y);
+        let fz = lab_f(z);
+
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+
+    pub fn to_oklab(&self) -> OKLab {
+        let l = (0.41222146 * self.r + 0.53633255 * self.g + 0.051445995 * self.b).cbrt();
+        let m = (0.2119035 * self.r + 0.6806995 * self.g + 0.10739696 * self.b).cbrt();
+        let s = (0.08830246 * self.r + 0.28171885 * self.g + 0.6299787 * self.b).cbrt();
+
+        OKLab {
+            l: 0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+            a: 1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+            b: 0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+        }
+    }
+}
+
+impl Lab {
+    pub fn to_rgb(&self) -> RGB {
+        let fy = (self.l + 16.0) / 116.0;
+        let fx = fy + self.a / 500.0;
+        let fz = fy - self.b / 200.0;
+
+        let x = lab_f_inv(fx) * D65_X;
+        let y = lab_f_inv(fy);
+        let z = lab_f_inv(fz) * D65_Z;
+
+        LinearRGB {
+            r: 3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+            g: -0.969266 * x + 1.8760108 * y + 0.0415560 * z,
+            b: 0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+        }.to_rgb()
+    }
+
+    /// Hue angle in degrees (0 - 360)
+    pub fn hue(&self) -> f32 {
+        self.b.atan2(self.a).to_degrees().rem_euclid(360.0)
+    }
+
+    /// CIEDE2000 color difference; ~1.0 is a just-noticeable difference
+    pub fn delta_e_2000(&self, other: &Lab) -> f32 {
+        let (l1, a1, b1) = (self.l as f64, self.a as f64, self.b as f64);
+        let (l2, a2, b2) = (other.l as f64, other.a as f64, other.b as f64);
+
+        let c1 = (a1 * a1 + b1 * b1).sqrt();
+        let c2 = (a2 * a2 + b2 * b2).sqrt();
+        let c_bar7 = ((c1 + c2) / 2.0).powi(7);
+        let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt());
+
+        let a1p = a1 * (1.0 + g);
+        let a2p = a2 * (1.0 + g);
+        let c1p = (a1p * a1p + b1 * b1).sqrt();
+        let c2p = (a2p * a2p + b2 * b2).sqrt();
+
+        let hue_of = |b: f64, a: f64| -> f64 {
+            if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) }
+        };
+        let h1p = hue_of(b1, a1p);
+        let h2p = hue_of(b2, a2p);
+
+        let dl = l2 - l1;
+        let dc = c2p - c1p;
+        let dh = if c1p * c2p == 0.0 {
+            0.0
+        } else if (h2p - h1p).abs() <= 180.0 {
+            h2p - h1p
+        } else if h2p - h1p > 180.0 {
+            h2p - h1p - 360.0
+        } else {
+            h2p - h1p + 360.0
+        };
+        let dh_big = 2.0 * (c1p * c2p).sqrt() * (dh.to_radians() / 2.0).sin();
+
+        let l_bar = (l1 + l2) / 2.0;
+        let c_bar_p = (c1p + c2p) / 2.0;
+        let h_bar_p = if c1p * c2p == 0.0 {
+            h1p + h2p
+        } else if (h1p - h2p).abs() <= 180.0 {
+            (h1p + h2p) / 2.0
+        } else if h1p + h2p < 360.0 {
+            (h1p + h2p + 360.0) / 2.0
+        } else {
+            (h1p + h2p - 360.0) / 2.0
+        };
+
+        let t = 1.0 - 0.17 * (h_bar_p - 30.0).to_radians().cos()
+            + 0.24 * (2.0 * h_bar_p).to_radians().cos()
+            + 0.32 * (3.0 * h_bar_p + 6.0).to_radians().cos()
+            - 0.20 * (4.0 * h_bar_p - 63.0).to_radians().cos();
+        let d_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+        let c_bar_p7 = c_bar_p.powi(7);
+        let r_c = 2.0 * (c_bar_p7 / (c_bar_p7 + 25f64.powi(7))).sqrt();
+        let l_term = (l_bar - 50.0).powi(2);
+        let s_l = 1.0 + 0.015 * l_term / (20.0 + l_term).sqrt();
+        let s_c = 1.0 + 0.045 * c_bar_p;
+        let s_h = 1.0 + 0.015 * c_bar_p * t;
+        let r_t = -(2.0 * d_theta).to_radians().sin() * r_c;
+
+        let l_part = dl / s_l;
+        let c_part = dc / s_c;
+        let h_part = dh_big / s_h;
+        (l_part * l_part + c_part * c_part + h_part * h_part + r_t * c_part * h_part).sqrt() as f32
+    }
+}
+
+impl OKLab {
+    pub fn to_rgb(&self) -> RGB {
+        let l = (self.l + 0.39633778 * self.a + 0.21580376 * self.b).powi(3);
+        let m = (self.l - 0.105561346 * self.a - 0.06385417 * self.b).powi(3);
+        let s = (self.l - 0.08948418 * self.a - 1.2914855 * self.b).powi(3);
+
+        LinearRGB {
+            r: 4.0767417 * l - 3.3077116 * m + 0.23096994 * s,
+            g: -1.268438 * l + 2.6097574 * m - 0.34131938 * s,
+            b: -0.0041960863 * l - 0.7034186 * m + 1.7076147 * s,
+        }.to_rgb()
+    }
+
+    /// Hue angle in degrees (0 - 360)
+    pub fn hue(&self) -> f32 {
+        self.b.atan2(self.a).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Euclidean distance; OKLab is close enough to uniform that this is perceptual
+    pub fn distance(&self, other: &OKLab) -> f32 {
+        let dl = self.l - other.l;
+        let da = self.a - other.a;
+        let db = self.b - other.b;
+        (dl * dl + da * da + db * db).sqrt()
+    }
+}
+
+impl YCbCr {
+    pub fn to_rgb(&self, matrix: YCbCrMatrix) -> RGB {
+        let (kr, kb) = matrix.coefficients();
+        let kg = 1.0 - kr - kb;
+        let cb = self.cb - 128.0;
+        let cr = self.cr - 128.0;
+
+        let r = self.y + 2.0 * (1.0 - kr) * cr;
+        let b = self.y + 2.0 * (1.0 - kb) * cb;
+        let g = (self.y - kr * r - kb * b) / kg;
+
+        RGB {
+            r: r.round().clamp(0.0, 255.0) as u8,
+            g: g.round().clamp(0.0, 255.0) as u8,
+            b: b.round().clamp(0.0, 255.0) as u8,
+        }
+    }
+}
+
+impl YCbCrMatrix {
+    /// (Kr, Kb) luma coefficients
+    fn coefficients(&self) -> (f32, f32) {
+        match self {
+            YCbCrMatrix::Bt601 => (0.299, 0.114),
+            YCbCrMatrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// Shortest distance between two hue angles in degrees (0 - 180)
+pub fn angular_distance(a: f32, b: f32) -> f32 {
+    let diff = (a - b).rem_euclid(360.0);
+    diff.min(360.0 - diff)
+}
+
+/// Circular mean of hue angles in degrees; None if empty or the angles cancel out
+pub fn circular_mean(angles: impl IntoIterator<Item = f32>) -> Option<f32> {
+    let mut stats = CircularStats::new();
+    for angle in angles {
+        stats.add(angle);
+    }
+    stats.mean()
+}
+
+/// Running statistics over hue angles (degrees) that respect the 0/360 wrap:
+/// 355° and 5° average to 0°, not 180°
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CircularStats {
+    sin_sum: f64,
+    cos_sum: f64,
+    weight: f64,
+}
+
+impl CircularStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, degrees: f32) {
+        self.add_weighted(degrees, 1.0);
+    }
+
+    pub fn add_weighted(&mut self, degrees: f32, weight: f32) {
+        let radians = (degrees as f64).to_radians();
+        self.sin_sum += radians.sin() * weight as f64;
+        self.cos_sum += radians.cos() * weight as f64;
+        self.weight += weight as f64;
+    }
+
+    /// Undo an earlier `add_weighted` with the same angle and weight
+    pub fn remove_weighted(&mut self, degrees: f32, weight: f32) {
+        self.add_weighted(degrees, -weight);
+    }
+
+    pub fn merge(&mut self, other: &CircularStats) {
+        self.sin_sum += other.sin_sum;
+        self.cos_sum += other.cos_sum;
+        self.weight += other.weight;
+    }
+
+    /// Total weight added so far
+    pub fn weight(&self) -> f32 {
+        self.weight as f32
+    }
+
+    /// Mean angle in [0, 360)
+    pub fn mean(&self) -> Option<f32> {
+        if self.weight <= 0.0 || self.resultant_length() < 1e-6 {
+            return None;
+        }
+        Some(self.sin_sum.atan2(self.cos_sum).to_degrees().rem_euclid(360.0) as f32)
+    }
+
+    /// Mean resultant length: 1 when every angle is equal, 0 when they cancel out
+    pub fn resultant_length(&self) -> f32 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        (self.sin_sum.hypot(self.cos_sum) / self.weight) as f32
+    }
+
+    /// Circular variance, 1 - resultant length (0 = identical, 1 = fully spread)
+    pub fn variance(&self) -> f32 {
+        1.0 - self.resultant_length()
+    }
+}
+
+const D65_X: f32 = 0.95047;
+const D65_Z: f32 = 1.08883;
+
+#[inline]
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline]
+fn to_u8(c: f32) -> u8 {
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    const DELTA: f32 = 6.0 / 29.0;
+    if t > DELTA {
+        t * t * t
+    } else {
+        3.0 * DELTA * DELTA * (t - 4.0 / 29.0)
     }
 }
 
@@ -139,14 +526,164 @@ mod tests {
     fn test_is_chromakey_candidate() {
         // High saturation, high value -> Candidate
         let hsv = HSV { h: 120.0, s: 0.8, v: 0.8 };
-        assert!(hsv.is_chromakey_candidate());
+        assert!(hsv.is_chromakey_candidate(0.3));
 
         // Low saturation -> Not candidate
         let hsv_pale = HSV { h: 120.0, s: 0.2, v: 0.8 };
-        assert!(!hsv_pale.is_chromakey_candidate());
+        assert!(!hsv_pale.is_chromakey_candidate(0.3));
+        assert!(hsv_pale.is_chromakey_candidate(0.1));
 
         // Low value -> Not candidate
         let hsv_dark = HSV { h: 120.0, s: 0.8, v: 0.1 };
-        assert!(!hsv_dark.is_chromakey_candidate());
+        assert!(!hsv_dark.is_chromakey_candidate(0.3));
+
+        // Stricter saturation gate
+        assert!(!hsv.is_chromakey_candidate(0.85));
+    }
+
+    #[test]
+    fn test_linear_rgb_round_trip() {
+        let rgb = RGB { r: 12, g: 128, b: 240 };
+        let back = rgb.to_linear().to_rgb();
+        assert_eq!((back.r, back.g, back.b), (12, 128, 240));
+
+        let white = RGB { r: 255, g: 255, b: 255 }.to_linear();
+        assert!((white.r - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_rgb_to_lab() {
+        let white = RGB { r: 255, g: 255, b: 255 }.to_lab();
+        assert!((white.l - 100.0).abs() < 0.01);
+        assert!(white.a.abs() < 0.01 && white.b.abs() < 0.01);
+
+        // Reference values for sRGB red
+        let red = RGB { r: 255, g: 0, b: 0 }.to_lab();
+        assert!((red.l - 53.24).abs() < 0.05, "L = {}", red.l);
+        assert!((red.a - 80.09).abs() < 0.05, "a = {}", red.a);
+        assert!((red.b - 67.20).abs() < 0.05, "b = {}", red.b);
+
+        let back = red.to_rgb();
+        assert_eq!((back.r, back.g, back.b), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_rgb_to_oklab() {
+        // Reference values for sRGB red
+        let red = RGB { r: 255, g: 0, b: 0 }.to_oklab();
+        assert!((red.l - 0.6279).abs() < 0.001, "L = {}", red.l);
+        assert!((red.a - 0.2249).abs() < 0.001, "a = {}", red.a);
+        assert!((red.b - 0.1258).abs() < 0.001, "b = {}", red.b);
+
+        let green = RGB { r: 30, g: 200, b: 60 };
+        let back = green.to_oklab().to_rgb();
+        assert_eq!((back.r, back.g, back.b), (30, 200, 60));
+    }
+
+    #[test]
+    fn test_ycbcr_matrices() {
+        let gray = RGB { r: 128, g: 128, b: 128 };
+        for matrix in [YCbCrMatrix::Bt601, YCbCrMatrix::Bt709] {
+            let ycc = gray.to_ycbcr(matrix);
+            assert!((ycc.y - 128.0).abs() < 0.01);
+            assert!((ycc.cb - 128.0).abs() < 0.01 && (ycc.cr - 128.0).abs() < 0.01);
+        }
+
+        // BT.601 and BT.709 weigh green differently
+        let green = RGB { r: 0, g: 255, b: 0 };
+        assert!((green.to_ycbcr(YCbCrMatrix::Bt601).y - 149.685).abs() < 0.01);
+        assert!((green.to_ycbcr(YCbCrMatrix::Bt709).y - 182.376).abs() < 0.01);
+
+        for matrix in [YCbCrMatrix::Bt601, YCbCrMatrix::Bt709] {
+            let rgb = RGB { r: 40, g: 210, b: 90 };
+            let back = rgb.to_ycbcr(matrix).to_rgb(matrix);
+            assert_eq!((back.r, back.g, back.b), (40, 210, 90));
+        }
+    }
+
+    #[test]
+    fn test_delta_e_2000_reference_pairs() {
+        // Pairs from Sharma, Wu & Dalal's CIEDE2000 test data
+        let pairs = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 2.5, 0.0), (50.0, 0.0, -2.5), 4.3065),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            ((60.2574, -34.0099, 36.2677), (60.4626, -34.1751, 39.4387), 1.2644),
+            ((22.7233, 20.0904, -46.6940), (23.0331, 14.9730, -42.5619), 2.0373),
+        ];
+
+        for ((l1, a1, b1), (l2, a2, b2), expected) in pairs {
+            let lab1 = Lab { l: l1, a: a1, b: b1 };
+            let lab2 = Lab { l: l2, a: a2, b: b2 };
+            let de = lab1.delta_e_2000(&lab2);
+            assert!((de - expected).abs() < 1e-3, "Expected {}, got {}", expected, de);
+            assert!((lab2.delta_e_2000(&lab1) - expected).abs() < 1e-3);
+        }
+
+        let same = Lab { l: 50.0, a: 10.0, b: 10.0 };
+        assert_eq!(same.delta_e_2000(&same), 0.0);
+    }
+
+    #[test]
+    fn test_oklab_distance_and_hue() {
+        let green = RGB { r: 0, g: 255, b: 0 }.to_oklab();
+        let near = RGB { r: 10, g: 245, b: 10 }.to_oklab();
+        let blue = RGB { r: 0, g: 0, b: 255 }.to_oklab();
+
+        assert!(green.distance(&near) < green.distance(&blue));
+        assert_eq!(green.distance(&green), 0.0);
+        assert!((green.hue() - 142.5).abs() < 1.0, "OKLab green hue = {}", green.hue());
+    }
+
+    #[test]
+    fn test_angular_distance() {
+        assert_eq!(angular_distance(10.0, 350.0), 20.0);
+        assert_eq!(angular_distance(350.0, 10.0), 20.0);
+        assert_eq!(angular_distance(0.0, 180.0), 180.0);
+        assert!((angular_distance(-5.0, 365.0) - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_circular_mean_red_wraps() {
+        // Red key spanning the 0/360 boundary
+        let mean = circular_mean([355.0, 5.0, 358.0, 2.0]).unwrap();
+        assert!(angular_distance(mean, 0.0) < 0.01, "red mean = {}", mean);
+
+        // Magenta-red keys lean below 360
+        let mean = circular_mean([340.0, 350.0, 10.0]).unwrap();
+        assert!(angular_distance(mean, 353.3) < 0.1, "magenta-red mean = {}", mean);
+
+        assert_eq!(circular_mean([]), None);
+        assert_eq!(circular_mean([90.0, 270.0]), None);
+    }
+
+    #[test]
+    fn test_circular_variance_and_merge() {
+        let mut tight = CircularStats::new();
+        tight.add(358.0);
+        tight.add(2.0);
+        assert!(tight.variance() < 0.001);
+
+        let mut spread = CircularStats::new();
+        for hue in [0.0, 90.0, 180.0, 270.0] {
+            spread.add(hue);
+        }
+        assert!(spread.variance() > 0.999);
+
+        // Weighting pulls the mean towards the heavier angle
+        let mut weighted = CircularStats::new();
+        weighted.add_weighted(300.0, 3.0);
+        weighted.add_weighted(0.0, 1.0);
+        let mean = weighted.mean().unwrap();
+        assert!(mean > 300.0 && mean < 330.0, "weighted mean = {}", mean);
+
+        let mut merged = tight;
+        merged.merge(&weighted);
+        assert_eq!(merged.weight(), 6.0);
+
+        // Removing an angle restores the mean without it
+        merged.remove_weighted(300.0, 3.0);
+        let mean = merged.mean().unwrap();
+        assert!(angular_distance(mean, 0.0) < 0.01, "mean after removal = {}", mean);
     }
 }
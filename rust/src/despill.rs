@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use crate::detection::{ChromakeyResult, DetectError, check_range};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DespillAlgorithm {
+    GreenLimit,     // Clamp green to max(red, blue)
+    BlueLimit,      // Clamp blue to max(red, green)
+    Average,        // Clamp the key's dominant channel to the mean of the other two
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DespillConfig {
+    pub algorithm: Option<DespillAlgorithm>, // Default: None (pick from key hue)
+    pub strength: f32,                       // Default: 1.0 (full suppression)
+}
+
+impl Default for DespillConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: None,
+            strength: 1.0,
+        }
+    }
+}
+
+impl DespillConfig {
+    /// Reject out-of-range (or NaN) fields
+    pub fn validate(&self) -> Result<(), DetectError> {
+        check_range("strength", self.strength, 0.0, 1.0)?;
+        Ok(())
+    }
+}
+
+impl DespillAlgorithm {
+    /// Pick the conventional algorithm for a key: limit green on green
+    /// screens, limit blue on blue screens, average for everything else
+    pub fn for_key(key: &ChromakeyResult) -> Self {
+        let hue = key.hue.rem_euclid(360.0);
+        if (75.0..165.0).contains(&hue) {
+            DespillAlgorithm::GreenLimit
+        } else if (195.0..285.0).contains(&hue) {
+            DespillAlgorithm::BlueLimit
+        } else {
+            DespillAlgorithm::Average
+        }
+    }
+}
+
+/// Suppress key color spill in an RGBA buffer, in place.
+/// Alpha is left untouched.
+pub fn despill(pixels: &mut [u8], key: &ChromakeyResult, config: &DespillConfig) {
+    // Grayscale keys don't tint the foreground
+    if key.color.to_hsv().s < 0.15 {
+        return;
+    }
+
+    let algorithm = config.algorithm.unwrap_or_else(|| DespillAlgorithm::for_key(key));
+    let strength = config.strength.clamp(0.0, 1.0);
+
+    // Channel the Average algorithm limits: whichever dominates the key color
+    let dominant = {
+        let c = key.color;
+        if c.g >= c.r && c.g >= c.b {
+            1
+        } else if c.b >= c.r {
+            2
+        } else {
+            0
+        }
+    };
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        let r = pixel[0] as f32;
+        let g = pixel[1] as f32;
+        let b = pixel[2] as f32;
+
+        let (channel, limit) = match algorithm {
+            DespillAlgorithm::GreenLimit => (1, r.max(b)),
+            DespillAlgorithm::BlueLimit => (2, r.max(g)),
+            DespillAlgorithm::Average => {
+                let limit = match dominant {
+                    0 => (g + b) / 2.0,
+                    1 => (r + b) / 2.0,
+                    _ => (r + g) / 2.0,
+                };
+                (dominant, limit)
+            }
+        };
+
+        let value = pixel[channel] as f32;
+        if value > limit {
+            let suppressed = value - (value - limit) * strength;
+            pixel[channel] = suppressed.round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::RGB;
+    use crate::detection::DetectionMethod;
+
+    fn key(r: u8, g: u8, b: u8, hue: f32) -> ChromakeyResult {
+        ChromakeyResult {
+            color: RGB { r, g, b },
+            confidence: 0.9,
+            coverage: 0.5,
+            hue,
+            method_used: DetectionMethod::Hybrid,
+        }
+    }
+
+    #[test]
+    fn test_algorithm_selection() {
+        assert_eq!(DespillAlgorithm::for_key(&key(0, 255, 0, 120.0)), DespillAlgorithm::GreenLimit);
+        assert_eq!(DespillAlgorithm::for_key(&key(0, 0, 255, 240.0)), DespillAlgorithm::BlueLimit);
+        assert_eq!(DespillAlgorithm::for_key(&key(255, 0, 255, 300.0)), DespillAlgorithm::Average);
+    }
+
+    #[test]
+    fn test_green_spill_removed() {
+        // Greenish hair edge and a neutral pixel
+        let mut pixels = vec![
+            120, 180, 100, 200,
+            128, 128, 128, 255,
+        ];
+
+        despill(&mut pixels, &key(0, 255, 0, 120.0), &DespillConfig::default());
+
+        assert_eq!(&pixels[0..4], &[120, 120, 100, 200]);
+        assert_eq!(&pixels[4..8], &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_blue_spill_removed() {
+        let mut pixels = vec![100, 90, 200, 255];
+
+        despill(&mut pixels, &key(0, 0, 255, 240.0), &DespillConfig::default());
+
+        assert_eq!(&pixels[..], &[100, 90, 100, 255]);
+    }
+
+    #[test]
+    fn test_override_and_strength() {
+        let mut pixels = vec![100, 200, 60, 255];
+        let config = DespillConfig {
+            algorithm: Some(DespillAlgorithm::Average),
+            strength: 0.5,
+        };
+
+        despill(&mut pixels, &key(0, 255, 0, 120.0), &config);
+
+        // Limit is (100 + 60) / 2 = 80, half strength lands at 140
+        assert_eq!(&pixels[..], &[100, 140, 60, 255]);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(DespillConfig::default().validate().is_ok());
+        for strength in [f32::NAN, -0.5, 2.0] {
+            let config = DespillConfig { strength, ..DespillConfig::default() };
+            assert!(matches!(
+                config.validate(),
+                Err(DetectError::ConfigOutOfRange { field, .. }) if field == "strength"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_grayscale_key_is_noop() {
+        let mut pixels = vec![100, 200, 60, 255];
+
+        despill(&mut pixels, &key(250, 250, 250, 0.0), &DespillConfig::default());
+
+        assert_eq!(&pixels[..], &[100, 200, 60, 255]);
+    }
+}
@@ -1,27 +1,109 @@
+use std::borrow::Cow;
 use serde::{Deserialize, Serialize};
-use crate::color::RGB;
-use crate::histogram::ColorHistogram;
-use crate::clustering::{KMeans, Cluster};
+use crate::color::{RGB, ColorSpace, angular_distance};
+use crate::pixel_format::{ImageView, PixelLayout, PixelSource};
+use crate::yuv::{YuvChromakeyResult, YuvImage};
+use crate::histogram::{ColorHistogram, Peak};
+use crate::clustering::{KMeans, Cluster, ClusterReport, KScore, MINI_BATCH_MIN_SAMPLES, sample_stride};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DetectionConfig {
+    #[serde(alias = "minAreaPercentage")]
     pub min_area_percentage: f32,     // Default: 0.25 (25% of frame)
-    pub min_saturation: f32,           // Default: 0.6
+    #[serde(alias = "minSaturation")]
+    pub min_saturation: f32,           // Default: 0.3 (weaker colors can't be the key)
+    #[serde(alias = "grayscaleSaturation")]
+    pub grayscale_saturation: f32,     // Default: 0.15 (below this a pixel is gray)
+    #[serde(alias = "edgeSamplePercentage")]
     pub edge_sample_percentage: f32,   // Default: 0.15 (15% border)
+    #[serde(alias = "confidenceThreshold")]
     pub confidence_threshold: f32,     // Default: 0.7
+    #[serde(alias = "colorSpace")]
+    pub color_space: ColorSpace,       // Default: hsv (clustering + histogram hue)
+    #[serde(alias = "useAlpha")]
+    pub use_alpha: bool,               // Default: false (alpha channel ignored)
+    #[serde(alias = "minAlpha")]
+    pub min_alpha: f32,                // Default: 0.1 (with use_alpha, more transparent pixels are skipped)
+    #[serde(alias = "kmeansK")]
+    pub kmeans_k: usize,               // Default: 3 clusters
+    #[serde(alias = "kmeansMaxIterations")]
+    pub kmeans_max_iterations: usize,  // Default: 10
+    #[serde(alias = "kmeansTolerance")]
+    pub kmeans_tolerance: f32,         // Default: 0.01 (stop once <1% of samples change cluster)
+    #[serde(alias = "kmeansSeed")]
+    pub kmeans_seed: u64,              // Default: 0x5EED (k-means++ seeding)
+    #[serde(alias = "kmeansAutoK")]
+    pub kmeans_auto_k: bool,           // Default: false (use kmeans_k as is)
+    #[serde(alias = "kmeansMinK")]
+    pub kmeans_min_k: usize,           // Default: 2 (auto-k search range)
+    #[serde(alias = "kmeansMaxK")]
+    pub kmeans_max_k: usize,           // Default: 6
+    #[serde(alias = "kmeansBatchSize")]
+    pub kmeans_batch_size: usize,      // Default: 0 (always full k-means; e.g. 1024 switches very large frames to mini-batch)
 }
 
 impl Default for DetectionConfig {
     fn default() -> Self {
         Self {
             min_area_percentage: 0.25,
-            min_saturation: 0.6,
+            min_saturation: 0.3,
+            grayscale_saturation: 0.15,
             edge_sample_percentage: 0.15,
             confidence_threshold: 0.7,
+            color_space: ColorSpace::Hsv,
+            use_alpha: false,
+            min_alpha: 0.1,
+            kmeans_k: 3,
+            kmeans_max_iterations: 10,
+            kmeans_tolerance: 0.01,
+            kmeans_seed: 0x5EED,
+            kmeans_auto_k: false,
+            kmeans_min_k: 2,
+            kmeans_max_k: 6,
+            kmeans_batch_size: 0,
         }
     }
 }
 
+impl DetectionConfig {
+    /// Reject out-of-range (or NaN) fields before they reach the pipeline
+    pub fn validate(&self) -> Result<(), DetectError> {
+        check_range("min_area_percentage", self.min_area_percentage, 0.0, 1.0)?;
+        check_range("min_saturation", self.min_saturation, 0.0, 1.0)?;
+        check_range("grayscale_saturation", self.grayscale_saturation, 0.0, 1.0)?;
+        // Past 0.5 the top/bottom and left/right borders would overlap
+        check_range("edge_sample_percentage", self.edge_sample_percentage, 0.0, 0.5)?;
+        check_range("confidence_threshold", self.confidence_threshold, 0.0, 1.0)?;
+        check_range("min_alpha", self.min_alpha, 0.0, 1.0)?;
+        check_range("kmeans_k", self.kmeans_k as f32, 1.0, MAX_KMEANS_K as f32)?;
+        check_range("kmeans_max_iterations", self.kmeans_max_iterations as f32, 1.0, MAX_KMEANS_ITERATIONS as f32)?;
+        check_range("kmeans_tolerance", self.kmeans_tolerance, 0.0, 1.0)?;
+        if self.kmeans_auto_k {
+            // Silhouette needs at least two clusters to compare
+            check_range("kmeans_min_k", self.kmeans_min_k as f32, 2.0, MAX_KMEANS_K as f32)?;
+            check_range("kmeans_max_k", self.kmeans_max_k as f32, self.kmeans_min_k as f32, MAX_KMEANS_K as f32)?;
+        }
+        if self.kmeans_batch_size > 0 {
+            // A batch as large as the sample set mini-batch kicks in at saves nothing
+            check_range("kmeans_batch_size", self.kmeans_batch_size as f32, 1.0, MINI_BATCH_MIN_SAMPLES as f32)?;
+        }
+        Ok(())
+    }
+}
+
+// Upper bounds that keep clustering cost sane
+const MAX_KMEANS_K: usize = 16;
+const MAX_KMEANS_ITERATIONS: usize = 1000;
+
+pub(crate) fn check_range(field: &'static str, value: f32, min: f32, max: f32) -> Result<(), DetectError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(DetectError::ConfigOutOfRange { field: field.into(), value, min, max })
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChromakeyResult {
     pub color: RGB,
@@ -32,7 +114,7 @@ pub struct ChromakeyResult {
     pub method_used: DetectionMethod,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DetectionMethod {
     Edge,           // Analyzed border pixels
@@ -40,53 +122,381 @@ pub enum DetectionMethod {
     Hybrid,         // Combined both methods
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyCandidate {
+    #[serde(flatten)]
+    pub result: ChromakeyResult,
+    pub score: f32,                 // Ranking score (0.0 - 1.0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionStage {
+    FullFrame,
+    Edges,
+    Clusters,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionReason {
+    FullFrameConfident,     // Full-frame peak beat confidence_threshold, later stages skipped
+    HighestConfidence,      // Best of every stage that ran
+    NoCandidates,           // No stage produced a result
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StageReport {
+    pub candidates: Vec<ChromakeyResult>,   // Best first
+    pub samples: u32,                       // Pixels fed into the stage
+    pub stride: u32,                        // Pixel step used while sampling
+    pub time_ms: f64,
+    pub used: bool,                         // Whether detect_chromakey consults this stage
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DetectionDiagnostics {
+    pub full_frame: StageReport,
+    pub edges: StageReport,
+    pub clusters: StageReport,
+    pub kmeans_clusters: Vec<Cluster>,      // Raw clusters, before candidate filtering
+    pub kmeans_k: usize,                    // k used (chosen by auto-k if enabled)
+    pub kmeans_k_scores: Vec<KScore>,       // Silhouette per candidate k; empty without auto-k
+    pub selected: Option<ChromakeyResult>,
+    pub selected_stage: Option<DetectionStage>,
+    pub reason: SelectionReason,
+    pub confidence_threshold: f32,
+    pub error: Option<DetectError>,
+    pub total_time_ms: f64,
+}
+
+/// Why detection could not produce a key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DetectError {
+    InvalidDimensions { width: u32, height: u32 },
+    BufferTooShort { expected: usize, actual: usize },
+    Grayscale,                      // No colored pixels to key on
+    Transparent,                    // Every pixel was below min_alpha
+    NoDominantColor { best_coverage: f32, min_area_percentage: f32 },
+    Desaturated { saturation: f32, min_saturation: f32 },
+    InvalidRowStride { row_stride: usize, min: usize },
+    InvalidConfig { message: String },
+    InvalidKey { message: String },  // A key passed in (e.g. to build a matte) didn't parse
+    ConfigOutOfRange { field: Cow<'static, str>, value: f32, min: f32, max: f32 },
+}
+
+impl std::fmt::Display for DetectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetectError::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {}x{}", width, height)
+            }
+            DetectError::BufferTooShort { expected, actual } => {
+                write!(f, "pixel buffer too short: expected {} bytes, got {}", expected, actual)
+            }
+            DetectError::InvalidRowStride { row_stride, min } => {
+                write!(f, "row stride {} is shorter than a row of pixels ({} bytes)", row_stride, min)
+            }
+            DetectError::Grayscale => write!(f, "image has no colored pixels"),
+            DetectError::Transparent => write!(f, "image has no pixels above the alpha threshold"),
+            DetectError::NoDominantColor { best_coverage, min_area_percentage } => write!(
+                f,
+                "no color covers enough of the image: best coverage {:.1}%, need {:.1}%",
+                best_coverage * 100.0,
+                min_area_percentage * 100.0
+            ),
+            DetectError::Desaturated { saturation, min_saturation } => write!(
+                f,
+                "dominant color is too desaturated: saturation {:.2}, need {:.2}",
+                saturation, min_saturation
+            ),
+            DetectError::InvalidConfig { message } => write!(f, "invalid config: {}", message),
+            DetectError::InvalidKey { message } => write!(f, "invalid key: {}", message),
+            DetectError::ConfigOutOfRange { field, value, min, max } => write!(
+                f,
+                "invalid config: {} must be between {} and {}, got {}",
+                field, min, max, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DetectError {}
+
+// Candidates closer than this in hue are treated as the same key
+const CANDIDATE_HUE_TOLERANCE: f32 = 10.0;
+// Score bonus for each additional stage that found the same key
+const AGREEMENT_BONUS: f32 = 0.1;
+
 pub fn detect_chromakey(
     pixels: &[u8],
     width: u32,
     height: u32,
     config: &DetectionConfig,
-) -> Option<ChromakeyResult> {
+) -> Result<ChromakeyResult, DetectError> {
+    config.validate()?;
+    let image = ImageView::rgba(pixels, width, height)?;
+    detect_in_image(&image, config)
+}
+
+/// Same as `detect_chromakey` for buffers that aren't tightly packed RGBA
+/// (BGRA/ARGB/RGB24 channel orders, padded rows)
+pub fn detect_chromakey_with_layout(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    layout: &PixelLayout,
+    config: &DetectionConfig,
+) -> Result<ChromakeyResult, DetectError> {
+    config.validate()?;
+    let image = ImageView::new(pixels, width, height, *layout)?;
+    detect_in_image(&image, config)
+}
+
+/// Detect the key color straight from I420/NV12 planes.
+/// Every stage samples at the chroma planes' native (subsampled) resolution.
+pub fn detect_chromakey_yuv(image: &YuvImage, config: &DetectionConfig) -> Result<YuvChromakeyResult, DetectError> {
+    config.validate()?;
+    let result = detect_in_image(&image.chroma(), config)?;
+    let (cb, cr) = image.cb_cr(result.color);
+    Ok(YuvChromakeyResult { result, cb, cr })
+}
+
+pub(crate) fn detect_in_image<S: PixelSource>(image: &S, config: &DetectionConfig) -> Result<ChromakeyResult, DetectError> {
     // New strategy for robust detection:
     // 1. Full-frame histogram analysis (samples entire image)
     // 2. If inconclusive, try edge-based analysis
     // 3. If still inconclusive, fall back to clustering
 
     // Step 1: Analyze full frame (most robust)
-    if let Some(full_result) = analyze_full_frame(pixels, width, height, config) {
+    let histogram = sample_full_frame(image, config);
+    if let Some(full_result) = analyze_full_frame(&histogram, config).into_iter().next() {
         if full_result.confidence > config.confidence_threshold {
-            return Some(full_result);
+            return Ok(full_result);
         }
 
         // Step 2: Try edge-based analysis
-        let edge_result = analyze_edges(pixels, width, height, config);
+        let edge_result = analyze_edges(&sample_edges(image, config), config).into_iter().next();
 
         // Step 3: Try clustering if needed
-        let cluster_result = analyze_clusters(pixels, width, height, config);
+        let cluster_result = analyze_clusters(&run_kmeans(image, config).clusters, config).into_iter().next();
 
         // Return best result from all methods
         return choose_best_result(
             Some(full_result),
             choose_best_result(edge_result, cluster_result)
-        );
+        ).ok_or_else(|| no_key_error(&histogram, config));
     }
 
     // Fallback: Try edges and clusters
-    let edge_result = analyze_edges(pixels, width, height, config);
-    let cluster_result = analyze_clusters(pixels, width, height, config);
+    let edge_result = analyze_edges(&sample_edges(image, config), config).into_iter().next();
+    let cluster_result = analyze_clusters(&run_kmeans(image, config).clusters, config).into_iter().next();
     choose_best_result(edge_result, cluster_result)
+        .ok_or_else(|| no_key_error(&histogram, config))
 }
 
-fn analyze_full_frame(
+/// Collect every candidate from all three stages, merged by hue and ranked.
+/// Keys found by several stages score higher than single-stage finds.
+pub fn detect_candidates(
     pixels: &[u8],
     width: u32,
     height: u32,
     config: &DetectionConfig,
-) -> Option<ChromakeyResult> {
-    let mut histogram = ColorHistogram::new();
+) -> Result<Vec<KeyCandidate>, DetectError> {
+    config.validate()?;
+    let image = &ImageView::rgba(pixels, width, height)?;
+
+    let mut results = analyze_full_frame(&sample_full_frame(image, config), config);
+    results.extend(analyze_edges(&sample_edges(image, config), config));
+    results.extend(analyze_clusters(&run_kmeans(image, config).clusters, config));
+
+    // Merge strongest first so each group keeps its most confident member
+    results.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    let mut merged: Vec<(ChromakeyResult, Vec<DetectionMethod>)> = Vec::new();
+    for result in results {
+        let is_grayscale = result.color.to_hsv().s < config.grayscale_saturation;
+        let existing = merged.iter_mut().find(|(kept, _)| {
+            // Grayscale hue is meaningless, so never merge across the gray/color divide
+            angular_distance(result.hue, kept.hue) < CANDIDATE_HUE_TOLERANCE && (kept.color.to_hsv().s < config.grayscale_saturation) == is_grayscale
+        });
+
+        match existing {
+            Some((_, methods)) => {
+                if !methods.contains(&result.method_used) {
+                    methods.push(result.method_used);
+                }
+            }
+            None => {
+                let method = result.method_used;
+                merged.push((result, vec![method]));
+            }
+        }
+    }
+
+    let mut candidates: Vec<KeyCandidate> = merged.into_iter().map(|(result, methods)| {
+        let bonus = AGREEMENT_BONUS * (methods.len() - 1) as f32;
+        KeyCandidate {
+            score: (result.confidence + bonus).min(1.0),
+            result,
+        }
+    }).collect();
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(candidates)
+}
+
+/// Run every stage and report what each one produced, which result
+/// `detect_chromakey` would pick, and why.
+/// Only invalid input is an error; "no key found" is reported in the diagnostics.
+pub fn detect_chromakey_with_diagnostics(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    config: &DetectionConfig,
+) -> Result<DetectionDiagnostics, DetectError> {
+    config.validate()?;
+    let image = &ImageView::rgba(pixels, width, height)?;
+    let start = now_ms();
+
+    // Unlike detect_chromakey, every stage runs so the report is complete
+    let stage_start = now_ms();
+    let full_histogram = sample_full_frame(image, config);
+    let full_frame = StageReport {
+        candidates: analyze_full_frame(&full_histogram, config),
+        samples: full_histogram.total_pixels,
+        stride: full_frame_stride(image.pixel_count()) as u32,
+        time_ms: now_ms() - stage_start,
+        used: true,
+    };
+
+    let stage_start = now_ms();
+    let edge_histogram = sample_edges(image, config);
+    let mut edges = StageReport {
+        candidates: analyze_edges(&edge_histogram, config),
+        samples: edge_histogram.total_pixels,
+        stride: 1,
+        time_ms: now_ms() - stage_start,
+        used: true,
+    };
+
+    let stage_start = now_ms();
+    let pixel_count = image.pixel_count();
+    let cluster_stride = sample_stride(pixel_count);
+    let kmeans = run_kmeans(image, config);
+    let mut clusters = StageReport {
+        candidates: analyze_clusters(&kmeans.clusters, config),
+        samples: pixel_count.div_ceil(cluster_stride) as u32,
+        stride: cluster_stride as u32,
+        time_ms: now_ms() - stage_start,
+        used: true,
+    };
 
-    // Sample the entire frame (use stride for performance on very large images)
-    let total_pixels = (width * height) as usize;
-    let stride = if total_pixels > 500_000 {
+    // Mirror the selection logic of detect_chromakey
+    let full_best = full_frame.candidates.first().cloned();
+    let (selected, reason) = match full_best {
+        Some(full) if full.confidence > config.confidence_threshold => {
+            edges.used = false;
+            clusters.used = false;
+            (Some(full), SelectionReason::FullFrameConfident)
+        }
+        _ => {
+            let best = choose_best_result(
+                full_best,
+                choose_best_result(edges.candidates.first().cloned(), clusters.candidates.first().cloned()),
+            );
+            let reason = if best.is_some() {
+                SelectionReason::HighestConfidence
+            } else {
+                SelectionReason::NoCandidates
+            };
+            (best, reason)
+        }
+    };
+
+    let selected_stage = selected.as_ref().map(|result| match result.method_used {
+        DetectionMethod::Hybrid => DetectionStage::FullFrame,
+        DetectionMethod::Edge => DetectionStage::Edges,
+        DetectionMethod::Cluster => DetectionStage::Clusters,
+    });
+    let error = match selected {
+        Some(_) => None,
+        None => Some(no_key_error(&full_histogram, config)),
+    };
+
+    Ok(DetectionDiagnostics {
+        full_frame,
+        edges,
+        clusters,
+        kmeans_clusters: kmeans.clusters,
+        kmeans_k: kmeans.k,
+        kmeans_k_scores: kmeans.k_scores,
+        selected,
+        selected_stage,
+        reason,
+        confidence_threshold: config.confidence_threshold,
+        error,
+        total_time_ms: now_ms() - start,
+    })
+}
+
+// Milliseconds from an arbitrary epoch; std::time::Instant is unavailable in the browser
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+fn no_key_error(histogram: &ColorHistogram, config: &DetectionConfig) -> DetectError {
+    if histogram.total_pixels == 0 {
+        return DetectError::Transparent;
+    }
+    if histogram.colored_pixels() == 0 {
+        return DetectError::Grayscale;
+    }
+
+    let peaks = histogram.find_peaks(0.0);
+    let best = peaks.iter().max_by(|a, b| a.percentage.total_cmp(&b.percentage));
+    match best {
+        // Large enough, so it must have been rejected by the saturation gate
+        Some(peak) if peak.percentage >= config.min_area_percentage => DetectError::Desaturated {
+            saturation: peak.average_color.to_hsv().s,
+            min_saturation: config.min_saturation,
+        },
+        _ => DetectError::NoDominantColor {
+            best_coverage: best.map_or(0.0, |p| p.percentage),
+            min_area_percentage: config.min_area_percentage,
+        },
+    }
+}
+
+// Grayscale backdrops are valid keys; colored peaks must clear min_saturation
+fn is_key_peak(peak: &Peak, config: &DetectionConfig) -> bool {
+    peak.is_grayscale || peak.average_color.to_hsv().s >= config.min_saturation
+}
+
+fn peak_to_result(peak: &Peak, method: DetectionMethod) -> ChromakeyResult {
+    // Use the actual average RGB color from the histogram, not a reconstruction
+    ChromakeyResult {
+        color: peak.average_color,
+        confidence: peak.percentage.min(1.0),
+        coverage: peak.percentage,
+        hue: peak.hue,
+        method_used: method,
+    }
+}
+
+fn full_frame_stride(total_pixels: usize) -> usize {
+    // Use stride for performance on very large images
+    if total_pixels > 500_000 {
         // For very large images, sample every 4th pixel
         4
     } else if total_pixels > 100_000 {
@@ -95,136 +505,119 @@ fn analyze_full_frame(
     } else {
         // For normal images, sample every pixel
         1
-    };
+    }
+}
 
-    // Helper to get pixel safely
-    let get_pixel = |x: u32, y: u32| -> Option<RGB> {
-        if x >= width || y >= height {
-            return None;
-        }
-        let idx = ((y * width + x) * 4) as usize;
-        if idx + 2 < pixels.len() {
-            Some(RGB {
-                r: pixels[idx],
-                g: pixels[idx + 1],
-                b: pixels[idx + 2],
-            })
-        } else {
-            None
-        }
-    };
+fn sample_full_frame<S: PixelSource>(image: &S, config: &DetectionConfig) -> ColorHistogram {
+    let mut histogram = ColorHistogram::with_grayscale_saturation(config.grayscale_saturation)
+        .in_color_space(config.color_space);
 
-    // Sample the full frame
-    for y in (0..height).step_by(stride) {
-        for x in (0..width).step_by(stride) {
-            if let Some(p) = get_pixel(x, y) {
-                histogram.add_pixel(p);
-            }
+    // Sample the entire frame
+    let stride = full_frame_stride(image.pixel_count());
+    for y in (0..image.height()).step_by(stride) {
+        for x in (0..image.width()).step_by(stride) {
+            add_sample(&mut histogram, image, x, y, config);
         }
     }
 
+    histogram
+}
+
+/// Add one pixel, skipping or weighting it by alpha when `use_alpha` is set
+fn add_sample<S: PixelSource>(histogram: &mut ColorHistogram, image: &S, x: u32, y: u32, config: &DetectionConfig) {
+    if !config.use_alpha {
+        histogram.add_pixel(image.pixel(x, y));
+        return;
+    }
+
+    let alpha = image.alpha(x, y);
+    if alpha > 0 && alpha >= min_alpha_byte(config) {
+        histogram.add_pixel_weighted(image.pixel(x, y), alpha as u32);
+    }
+}
+
+fn min_alpha_byte(config: &DetectionConfig) -> u8 {
+    (config.min_alpha * 255.0).round() as u8
+}
+
+fn analyze_full_frame(histogram: &ColorHistogram, config: &DetectionConfig) -> Vec<ChromakeyResult> {
     // Find dominant color across entire frame
     let peaks = histogram.find_peaks(config.min_area_percentage);
 
-    if let Some(best_peak) = peaks.first() {
-        // Use the actual average RGB color from the histogram
-        Some(ChromakeyResult {
-            color: best_peak.average_color,
-            confidence: best_peak.percentage.min(1.0),
-            coverage: best_peak.percentage,
-            hue: best_peak.hue,
-            method_used: DetectionMethod::Hybrid,  // Full-frame is a hybrid approach
-        })
-    } else {
-        None
-    }
+    // Full-frame is a hybrid approach
+    peaks.iter()
+        .filter(|peak| is_key_peak(peak, config))
+        .map(|peak| peak_to_result(peak, DetectionMethod::Hybrid))
+        .collect()
 }
 
-fn analyze_edges(
-    pixels: &[u8],
-    width: u32,
-    height: u32,
-    config: &DetectionConfig,
-) -> Option<ChromakeyResult> {
-    let mut histogram = ColorHistogram::new();
+fn sample_edges<S: PixelSource>(image: &S, config: &DetectionConfig) -> ColorHistogram {
+    let mut histogram = ColorHistogram::with_grayscale_saturation(config.grayscale_saturation)
+        .in_color_space(config.color_space);
+    let width = image.width();
+    let height = image.height();
     
     // Sample border pixels (top, bottom, left, right)
     let border_width = (width as f32 * config.edge_sample_percentage) as u32;
     let border_height = (height as f32 * config.edge_sample_percentage) as u32;
-    
-    // Helper to get pixel safely
-    let get_pixel = |x: u32, y: u32| -> Option<RGB> {
-        if x >= width || y >= height { return None; }
-        let idx = ((y * width + x) * 4) as usize;
-        if idx + 2 < pixels.len() {
-             Some(RGB {
-                r: pixels[idx],
-                g: pixels[idx + 1],
-                b: pixels[idx + 2],
-            })
-        } else {
-            None
-        }
-    };
 
     // Top and bottom edges
     for y in 0..border_height {
         for x in 0..width {
-            if let Some(p) = get_pixel(x, y) { histogram.add_pixel(p); }
-            if let Some(p) = get_pixel(x, height - 1 - y) { histogram.add_pixel(p); }
+            add_sample(&mut histogram, image, x, y, config);
+            add_sample(&mut histogram, image, x, height - 1 - y, config);
         }
     }
     
     // Left and right edges
     for x in 0..border_width {
         for y in border_height..height - border_height {
-             if let Some(p) = get_pixel(x, y) { histogram.add_pixel(p); }
-             if let Some(p) = get_pixel(width - 1 - x, y) { histogram.add_pixel(p); }
+            add_sample(&mut histogram, image, x, y, config);
+            add_sample(&mut histogram, image, width - 1 - x, y, config);
         }
     }
-    
+
+    histogram
+}
+
+fn analyze_edges(histogram: &ColorHistogram, config: &DetectionConfig) -> Vec<ChromakeyResult> {
     // Find dominant color in edges
     let peaks = histogram.find_peaks(0.05); // Lower threshold for edges
     
-    if let Some(best_peak) = peaks.first() {
-        // Use the actual average RGB color from the histogram
-        Some(ChromakeyResult {
-            color: best_peak.average_color,  // Real average color, not reconstructed
-            confidence: best_peak.percentage.min(1.0),
-            coverage: best_peak.percentage,
-            hue: best_peak.hue,
-            method_used: DetectionMethod::Edge,
-        })
-    } else {
-        None
+    peaks.iter()
+        .filter(|peak| is_key_peak(peak, config))
+        .map(|peak| peak_to_result(peak, DetectionMethod::Edge))
+        .collect()
+}
+
+fn run_kmeans<S: PixelSource>(image: &S, config: &DetectionConfig) -> ClusterReport {
+    let mut kmeans = KMeans::new(config.kmeans_k)
+        .with_max_iterations(config.kmeans_max_iterations)
+        .with_tolerance(config.kmeans_tolerance)
+        .with_seed(config.kmeans_seed)
+        .with_mini_batch(config.kmeans_batch_size)
+        .with_color_space(config.color_space);
+    if config.use_alpha {
+        kmeans = kmeans.with_alpha_threshold(min_alpha_byte(config));
+    }
+    if config.kmeans_auto_k {
+        kmeans = kmeans.with_auto_k(config.kmeans_min_k, config.kmeans_max_k);
     }
+    kmeans.find_clusters_with_report(image)
 }
 
-fn analyze_clusters(
-    pixels: &[u8],
-    width: u32,
-    height: u32,
-    _config: &DetectionConfig,
-) -> Option<ChromakeyResult> {
-    let kmeans = KMeans::new(3); // k=3 usually enough
-    let clusters = kmeans.find_clusters(pixels, width, height);
-    
-    // Filter for valid chromakey candidates
-    let valid_clusters: Vec<&Cluster> = clusters.iter()
-        .filter(|c| c.centroid.is_chromakey_candidate())
-        .collect();
-        
-    if let Some(best) = valid_clusters.first() {
-         Some(ChromakeyResult {
-            color: best.centroid.to_rgb(),
-            confidence: best.percentage.min(1.0),
-            coverage: best.percentage,
-            hue: best.centroid.h,
+fn analyze_clusters(clusters: &[Cluster], config: &DetectionConfig) -> Vec<ChromakeyResult> {
+    // Filter for valid chromakey candidates (clusters are already sorted by coverage)
+    clusters.iter()
+        .filter(|c| c.centroid.is_chromakey_candidate(config.min_saturation))
+        .map(|c| ChromakeyResult {
+            color: c.centroid.to_rgb(),
+            confidence: c.percentage.min(1.0),
+            coverage: c.percentage,
+            hue: c.centroid.h,
             method_used: DetectionMethod::Cluster,
         })
-    } else {
-        None
-    }
+        .collect()
 }
 
 fn choose_best_result(r1: Option<ChromakeyResult>, r2: Option<ChromakeyResult>) -> Option<ChromakeyResult> {
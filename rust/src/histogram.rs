@@ -1,11 +1,11 @@
-use crate::color::RGB;
+use crate::color::{RGB, ColorSpace};
 
 #[derive(Clone, Copy, Debug)]
 struct RGBAccumulator {
     r_sum: u64,
     g_sum: u64,
     b_sum: u64,
-    count: u32,
+    count: u64,
 }
 
 impl RGBAccumulator {
@@ -13,11 +13,18 @@ impl RGBAccumulator {
         Self { r_sum: 0, g_sum: 0, b_sum: 0, count: 0 }
     }
 
-    fn add(&mut self, rgb: RGB) {
-        self.r_sum += rgb.r as u64;
-        self.g_sum += rgb.g as u64;
-        self.b_sum += rgb.b as u64;
-        self.count += 1;
+    fn add(&mut self, rgb: RGB, weight: u32) {
+        self.r_sum += rgb.r as u64 * weight as u64;
+        self.g_sum += rgb.g as u64 * weight as u64;
+        self.b_sum += rgb.b as u64 * weight as u64;
+        self.count += weight as u64;
+    }
+
+    fn merge(&mut self, other: &RGBAccumulator) {
+        self.r_sum += other.r_sum;
+        self.g_sum += other.g_sum;
+        self.b_sum += other.b_sum;
+        self.count += other.count;
     }
 
     fn average(&self) -> RGB {
@@ -25,32 +32,48 @@ impl RGBAccumulator {
             return RGB { r: 0, g: 0, b: 0 };
         }
         RGB {
-            r: (self.r_sum / self.count as u64) as u8,
-            g: (self.g_sum / self.count as u64) as u8,
-            b: (self.b_sum / self.count as u64) as u8,
+            r: (self.r_sum / self.count) as u8,
+            g: (self.g_sum / self.count) as u8,
+            b: (self.b_sum / self.count) as u8,
         }
     }
 }
 
+// Bins and totals hold summed pixel weights (up to 255 per pixel with
+// alpha), which overflow u32 on large frames
 pub struct ColorHistogram {
-    hue_bins: Vec<u32>,                    // 360 bins for hue (0-359°)
+    hue_bins: Vec<u64>,                    // 360 bins for hue (0-359°)
     rgb_accumulators: Vec<RGBAccumulator>, // Store actual RGB values for each hue
-    saturation_bins: Vec<u32>,             // 100 bins for saturation
-    value_bins: Vec<u32>,                  // 100 bins for value/brightness (for grayscale)
+    saturation_bins: Vec<u64>,             // 100 bins for saturation
+    value_bins: Vec<u64>,                  // 100 bins for value/brightness (for grayscale)
     grayscale_accumulator: RGBAccumulator, // Accumulator for low-saturation pixels
-    grayscale_count: u32,                  // Count of grayscale pixels
+    grayscale_count: u64,                  // Count of grayscale pixels
+    grayscale_saturation: f32,             // Pixels below this saturation count as grayscale
+    color_space: ColorSpace,               // Space whose hue angle picks the bin
+    total_weight: u64,                     // Sum of pixel weights; equals total_pixels when unweighted
     pub total_pixels: u32,
 }
 
 pub struct Peak {
     pub hue: f32,
-    pub count: u32,
+    pub count: u64,
     pub percentage: f32,
     pub average_color: RGB,  // Add actual average RGB color
+    pub is_grayscale: bool,  // Built from the grayscale bucket rather than hue bins
+}
+
+impl Default for ColorHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ColorHistogram {
     pub fn new() -> Self {
+        Self::with_grayscale_saturation(0.15)
+    }
+
+    pub fn with_grayscale_saturation(grayscale_saturation: f32) -> Self {
         Self {
             hue_bins: vec![0; 360],
             rgb_accumulators: vec![RGBAccumulator::new(); 360],
@@ -58,43 +81,70 @@ impl ColorHistogram {
             value_bins: vec![0; 100],
             grayscale_accumulator: RGBAccumulator::new(),
             grayscale_count: 0,
+            grayscale_saturation,
+            color_space: ColorSpace::Hsv,
+            total_weight: 0,
             total_pixels: 0,
         }
     }
 
+    /// Bin by the hue angle of a perceptual space instead of HSV hue.
+    /// Grayscale gating still uses HSV saturation, and peaks still report HSV hue.
+    pub fn in_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
     pub fn add_pixel(&mut self, rgb: RGB) {
+        self.add_pixel_weighted(rgb, 1);
+    }
+
+    /// Add a pixel that counts `weight` times towards bins, averages and
+    /// percentages (e.g. its alpha). Weights must be on the same scale for
+    /// every pixel of a histogram.
+    pub fn add_pixel_weighted(&mut self, rgb: RGB, weight: u32) {
         let hsv = rgb.to_hsv();
+        self.total_pixels += 1;
+        self.total_weight += weight as u64;
 
         // Handle low-saturation pixels (grayscale: black, white, grays)
-        if hsv.s < 0.15 {
+        if hsv.s < self.grayscale_saturation {
             let value_idx = ((hsv.v * 99.0) as usize).min(99);
-            self.value_bins[value_idx] += 1;
-            self.grayscale_accumulator.add(rgb);
-            self.grayscale_count += 1;
-            self.total_pixels += 1;
+            self.value_bins[value_idx] += weight as u64;
+            self.grayscale_accumulator.add(rgb, weight);
+            self.grayscale_count += weight as u64;
             return;
         }
 
         // Handle colored pixels (hue-based)
-        let hue_idx = (hsv.h as usize).min(359);
+        let hue = match self.color_space {
+            ColorSpace::Hsv => hsv.h,
+            ColorSpace::Lab => rgb.to_lab().hue(),
+            ColorSpace::Oklab => rgb.to_oklab().hue(),
+        };
+        let hue_idx = (hue as usize).min(359);
         let sat_idx = ((hsv.s * 99.0) as usize).min(99);
 
-        self.hue_bins[hue_idx] += 1;
-        self.rgb_accumulators[hue_idx].add(rgb);
-        self.saturation_bins[sat_idx] += 1;
-        self.total_pixels += 1;
+        self.hue_bins[hue_idx] += weight as u64;
+        self.rgb_accumulators[hue_idx].add(rgb, weight);
+        self.saturation_bins[sat_idx] += weight as u64;
     }
     
+    /// Weight of pixels that landed in a hue bin rather than the grayscale bucket
+    pub fn colored_pixels(&self) -> u64 {
+        self.total_weight - self.grayscale_count
+    }
+
     pub fn find_peaks(&self, min_percentage: f32) -> Vec<Peak> {
         let mut peaks = Vec::new();
-        if self.total_pixels == 0 {
+        if self.total_weight == 0 {
             return peaks;
         }
 
         // Simple peak finding: look for local maxima that are above threshold
         // We consider a window of +/- 5 degrees
         let window = 5;
-        let threshold = (self.total_pixels as f32 * min_percentage) as u32;
+        let threshold = (self.total_weight as f64 * min_percentage as f64) as u64;
 
         for i in 0..360 {
             let count = self.hue_bins[i];
@@ -116,11 +166,8 @@ impl ColorHistogram {
             if is_peak {
                 // Sum up all pixels in the peak region (not just the single bin)
                 // This gives us the true coverage of this color
-                let mut region_count: u32 = count;
-                let mut region_r_sum: u64 = self.rgb_accumulators[i].r_sum;
-                let mut region_g_sum: u64 = self.rgb_accumulators[i].g_sum;
-                let mut region_b_sum: u64 = self.rgb_accumulators[i].b_sum;
-                let mut region_pixel_count: u32 = self.rgb_accumulators[i].count;
+                let mut region_count: u64 = count;
+                let mut region_color = self.rgb_accumulators[i];
 
                 // Expand outward from peak until we hit a valley
                 // Use 5-bin moving average to handle gaps from hue quantization
@@ -128,8 +175,8 @@ impl ColorHistogram {
                 let valley_threshold = count / 5;
 
                 // Helper to get 5-bin average (handles wraparound)
-                let get_avg = |center: usize| -> u32 {
-                    let mut sum = 0u32;
+                let get_avg = |center: usize| -> u64 {
+                    let mut sum = 0u64;
                     for offset in -2i32..=2 {
                         let idx = ((center as i32 + offset + 360) % 360) as usize;
                         sum += self.hue_bins[idx];
@@ -146,10 +193,7 @@ impl ColorHistogram {
                         break;
                     }
                     region_count += self.hue_bins[idx];
-                    region_r_sum += self.rgb_accumulators[idx].r_sum;
-                    region_g_sum += self.rgb_accumulators[idx].g_sum;
-                    region_b_sum += self.rgb_accumulators[idx].b_sum;
-                    region_pixel_count += self.rgb_accumulators[idx].count;
+                    region_color.merge(&self.rgb_accumulators[idx]);
                 }
 
                 // Check right side - use 5-bin average to bridge small gaps
@@ -161,34 +205,30 @@ impl ColorHistogram {
                         break;
                     }
                     region_count += self.hue_bins[idx];
-                    region_r_sum += self.rgb_accumulators[idx].r_sum;
-                    region_g_sum += self.rgb_accumulators[idx].g_sum;
-                    region_b_sum += self.rgb_accumulators[idx].b_sum;
-                    region_pixel_count += self.rgb_accumulators[idx].count;
+                    region_color.merge(&self.rgb_accumulators[idx]);
                 }
 
                 // Calculate average color from the entire region
-                let avg_color = if region_pixel_count > 0 {
-                    RGB {
-                        r: (region_r_sum / region_pixel_count as u64) as u8,
-                        g: (region_g_sum / region_pixel_count as u64) as u8,
-                        b: (region_b_sum / region_pixel_count as u64) as u8,
-                    }
-                } else {
-                    self.rgb_accumulators[i].average()
+                let avg_color = region_color.average();
+
+                // Bin index is only an HSV hue when binning in HSV
+                let hue = match self.color_space {
+                    ColorSpace::Hsv => i as f32,
+                    _ => avg_color.to_hsv().h,
                 };
 
                 peaks.push(Peak {
-                    hue: i as f32,
+                    hue,
                     count: region_count,
-                    percentage: region_count as f32 / self.total_pixels as f32,
+                    percentage: (region_count as f64 / self.total_weight as f64) as f32,
                     average_color: avg_color,
+                    is_grayscale: false,
                 });
             }
         }
 
         // Check for grayscale chromakey (black/white)
-        let grayscale_percentage = self.grayscale_count as f32 / self.total_pixels as f32;
+        let grayscale_percentage = (self.grayscale_count as f64 / self.total_weight as f64) as f32;
         if grayscale_percentage > min_percentage && self.grayscale_count > 0 {
             // Grayscale is significant - add it as a peak
             let avg_color = self.grayscale_accumulator.average();
@@ -199,16 +239,14 @@ impl ColorHistogram {
                 count: self.grayscale_count,
                 percentage: grayscale_percentage,
                 average_color: avg_color,
+                is_grayscale: true,
             });
         }
 
         // Sort: colored peaks (by count desc), then grayscale peaks (by count desc)
         // This ensures we prefer chromakey colors over grayscale backgrounds
         peaks.sort_by(|a, b| {
-            let a_is_grayscale = a.average_color.to_hsv().s < 0.15;
-            let b_is_grayscale = b.average_color.to_hsv().s < 0.15;
-
-            match (a_is_grayscale, b_is_grayscale) {
+            match (a.is_grayscale, b.is_grayscale) {
                 (false, true) => std::cmp::Ordering::Less,    // a (colored) before b (grayscale)
                 (true, false) => std::cmp::Ordering::Greater, // b (colored) before a (grayscale)
                 _ => b.count.cmp(&a.count),                   // same type: sort by count
@@ -250,7 +288,86 @@ mod tests {
         assert_eq!(hist.total_pixels, 10);
         assert_eq!(hist.grayscale_count, 10);
         // Hue bins should be empty since gray has no hue
-        assert_eq!(hist.hue_bins.iter().sum::<u32>(), 0);
+        assert_eq!(hist.hue_bins.iter().sum::<u64>(), 0);
+    }
+
+    #[test]
+    fn test_grayscale_saturation_threshold() {
+        // Pale green (saturation ~0.25)
+        let pale = RGB { r: 150, g: 200, b: 150 };
+
+        let mut default_hist = ColorHistogram::new();
+        default_hist.add_pixel(pale);
+        assert_eq!(default_hist.grayscale_count, 0);
+
+        let mut strict_hist = ColorHistogram::with_grayscale_saturation(0.3);
+        strict_hist.add_pixel(pale);
+        assert_eq!(strict_hist.grayscale_count, 1);
+        assert_eq!(strict_hist.colored_pixels(), 0);
+    }
+
+    #[test]
+    fn test_weighted_pixels() {
+        let mut hist = ColorHistogram::new();
+        for _ in 0..10 {
+            hist.add_pixel_weighted(RGB { r: 0, g: 200, b: 0 }, 255);
+        }
+        for _ in 0..10 {
+            hist.add_pixel_weighted(RGB { r: 0, g: 100, b: 0 }, 85);
+        }
+        for _ in 0..30 {
+            hist.add_pixel_weighted(RGB { r: 0, g: 0, b: 255 }, 51);
+        }
+
+        assert_eq!(hist.total_pixels, 50);
+        let peaks = hist.find_peaks(0.1);
+        assert_eq!(peaks.len(), 2);
+
+        // Green weighs 3400 of 4930 despite being only 20 of 50 pixels
+        assert!((peaks[0].hue - 120.0).abs() < 1.0);
+        assert!((peaks[0].percentage - 3400.0 / 4930.0).abs() < 0.001);
+        // Average is weighted too: (200 * 3 + 100) / 4
+        assert_eq!(peaks[0].average_color.g, 175);
+    }
+
+    #[test]
+    fn test_weights_past_u32() {
+        // Opaque edge samples of an 8K frame add up past u32::MAX; fill the
+        // bins directly rather than adding 20M pixels one by one
+        let mut hist = ColorHistogram::new();
+        let samples = 20_000_000u64;
+        hist.hue_bins[120] = samples * 255;
+        hist.rgb_accumulators[120].count = samples * 255;
+        hist.rgb_accumulators[120].g_sum = samples * 255 * 255;
+        hist.total_weight = samples * 255;
+        hist.add_pixel_weighted(RGB { r: 0, g: 0, b: 255 }, 255);
+
+        let peaks = hist.find_peaks(0.1);
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].count, samples * 255);
+        assert!(peaks[0].percentage > 0.999);
+        assert_eq!(peaks[0].average_color.g, 255);
+    }
+
+    #[test]
+    fn test_perceptual_hue_binning() {
+        for space in [ColorSpace::Lab, ColorSpace::Oklab] {
+            let mut hist = ColorHistogram::new().in_color_space(space);
+            for _ in 0..100 {
+                hist.add_pixel(RGB { r: 0, g: 255, b: 0 });
+            }
+            for _ in 0..50 {
+                hist.add_pixel(RGB { r: 0, g: 0, b: 255 });
+            }
+
+            let peaks = hist.find_peaks(0.1);
+
+            // Peaks still report HSV hue so results stay comparable
+            assert_eq!(peaks.len(), 2, "{:?}", space);
+            assert!((peaks[0].hue - 120.0).abs() < 1.0, "{:?}: {}", space, peaks[0].hue);
+            assert!((peaks[1].hue - 240.0).abs() < 1.0);
+            assert_eq!(peaks[0].count, 100);
+        }
     }
 
     #[test]
@@ -333,7 +450,7 @@ mod tests {
         // Green chromakey spread across 40 degrees (100-139) with gradual falloff
         // Peak around 120, tapering off on both sides
         for hue in 100..140 {
-            let distance_from_center = ((hue as i32) - 120).abs() as u32;
+            let distance_from_center = (hue - 120i32).unsigned_abs();
             let count = 100u32.saturating_sub(distance_from_center * 4); // Gradual falloff
             let hsv = crate::color::HSV { h: hue as f32, s: 0.9, v: 0.9 };
             let rgb = hsv.to_rgb();
@@ -343,7 +460,7 @@ mod tests {
         }
         // Calculate total green pixels
         let green_total: u32 = (100..140).map(|hue| {
-            let d = ((hue as i32) - 120).abs() as u32;
+            let d = (hue - 120i32).unsigned_abs();
             100u32.saturating_sub(d * 4)
         }).sum();
         println!("Green pixels: {}", green_total);
@@ -3,15 +3,42 @@ pub mod histogram;
 pub mod clustering;
 pub mod detection;
 pub mod video;
+pub mod matte;
+pub mod despill;
+pub mod pixel_format;
+pub mod yuv;
+pub mod tracking;
+pub mod codec;
 
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use wasm_bindgen::prelude::*;
-use crate::detection::{DetectionConfig, detect_chromakey};
-use crate::video::VideoAnalyzer;
+use crate::detection::{
+    ChromakeyResult, DetectionConfig, DetectError, detect_chromakey, detect_candidates,
+    detect_chromakey_with_diagnostics, detect_chromakey_with_layout, detect_chromakey_yuv,
+};
+use crate::pixel_format::{ImageView, PixelLayout};
+use crate::yuv::{YuvImage, YuvLayout};
+use crate::video::{SessionError, VideoAnalyzer, VideoConfig};
+use crate::matte::{MatteConfig, generate_matte};
+use crate::despill::{DespillConfig, despill};
+use crate::tracking::{KeyTracker, TrackingConfig};
 
 #[wasm_bindgen]
 pub struct ChromaDetect {
     config: DetectionConfig,
+    matte_config: MatteConfig,
+    despill_config: DespillConfig,
     video_analyzer: Option<VideoAnalyzer>,
+    video_config: VideoConfig,
+    tracking_config: TrackingConfig,
+    tracker: Option<KeyTracker>,
+}
+
+impl Default for ChromaDetect {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -24,46 +51,187 @@ impl ChromaDetect {
         
         Self {
             config: DetectionConfig::default(),
+            matte_config: MatteConfig::default(),
+            despill_config: DespillConfig::default(),
             video_analyzer: None,
+            video_config: VideoConfig::default(),
+            tracking_config: TrackingConfig::default(),
+            tracker: None,
         }
     }
     
     /// Analyze a single image
     /// pixels: RGBA pixel data (Uint8Array from canvas)
+    /// Throws a `DetectError` explaining why no key was found
     #[wasm_bindgen]
     pub fn detect_from_image(
         &self,
         pixels: &[u8],
         width: u32,
         height: u32,
-    ) -> JsValue {
-        match detect_chromakey(pixels, width, height, &self.config) {
-            Some(result) => serde_wasm_bindgen::to_value(&result).unwrap(),
-            None => JsValue::NULL,
-        }
+    ) -> Result<JsValue, JsValue> {
+        let result = detect_chromakey(pixels, width, height, &self.config).map_err(to_js_error)?;
+        Ok(serde_wasm_bindgen::to_value(&result)?)
     }
     
+    /// Analyze a single image in another pixel layout without converting it first
+    /// layout: `{ format: "bgra" | "argb" | "rgb24" | "bgr24" | "rgba", rowStride }`
+    #[wasm_bindgen]
+    pub fn detect_from_pixels(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        layout: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let layout: PixelLayout = config_from_js(layout)?;
+        let result = detect_chromakey_with_layout(pixels, width, height, &layout, &self.config)
+            .map_err(to_js_error)?;
+        Ok(serde_wasm_bindgen::to_value(&result)?)
+    }
+
+    /// Analyze an I420/NV12 frame without converting it to RGBA
+    /// data: Y plane followed by the chroma plane(s)
+    /// layout: `{ format: "i420" | "nv12", yStride, uvStride, matrix: "bt601" | "bt709", fullRange }`
+    /// The result also carries the key's `cb`/`cr`
+    #[wasm_bindgen]
+    pub fn detect_from_yuv(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        layout: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let layout: YuvLayout = config_from_js(layout)?;
+        let image = YuvImage::contiguous(data, width, height, layout).map_err(to_js_error)?;
+        let result = detect_chromakey_yuv(&image, &self.config).map_err(to_js_error)?;
+        let serializer = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+        Ok(result.serialize(&serializer)?)
+    }
+
+    /// Rank every key candidate found in a single image
+    /// Returns an array of results with a `score`, best first
+    #[wasm_bindgen]
+    pub fn detect_candidates(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<JsValue, JsValue> {
+        let candidates = detect_candidates(pixels, width, height, &self.config).map_err(to_js_error)?;
+        // Flattened candidates serialize as maps; emit plain objects instead of JS Maps
+        let serializer = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+        Ok(candidates.serialize(&serializer)?)
+    }
+
+    /// Run every detection stage and report candidates, sample counts,
+    /// strides, timings and which result was selected (for tuning)
+    #[wasm_bindgen]
+    pub fn detect_with_diagnostics(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<JsValue, JsValue> {
+        let report = detect_chromakey_with_diagnostics(pixels, width, height, &self.config)
+            .map_err(to_js_error)?;
+        Ok(serde_wasm_bindgen::to_value(&report)?)
+    }
+
+    /// Detect the key color and build its alpha matte in one call
+    /// Returns one alpha byte per pixel; throws a `DetectError` if no key was found
+    #[wasm_bindgen]
+    pub fn matte_from_image(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, JsValue> {
+        let key = detect_chromakey(pixels, width, height, &self.config).map_err(to_js_error)?;
+        generate_matte(pixels, width, height, &key, &self.matte_config).map_err(to_js_error)
+    }
+
+    /// Build an alpha matte for a previously detected key (e.g. a video consensus)
+    /// Throws a `DetectError` if the key can't be read or the buffer doesn't fit
+    #[wasm_bindgen]
+    pub fn matte_with_key(
+        &self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        key: JsValue,
+    ) -> Result<Vec<u8>, JsValue> {
+        let key = serde_wasm_bindgen::from_value(key)
+            .map_err(|e| to_js_error(DetectError::InvalidKey { message: e.to_string() }))?;
+        generate_matte(pixels, width, height, &key, &self.matte_config).map_err(to_js_error)
+    }
+
+    /// Suppress key color spill in RGBA pixels, in place
+    /// Throws a `DetectError` if the key can't be read
+    #[wasm_bindgen]
+    pub fn despill_with_key(&self, pixels: &mut [u8], key: JsValue) -> Result<(), JsValue> {
+        let key = serde_wasm_bindgen::from_value(key)
+            .map_err(|e| to_js_error(DetectError::InvalidKey { message: e.to_string() }))?;
+        despill(pixels, &key, &self.despill_config);
+        Ok(())
+    }
+
     /// Initialize video analysis session
     #[wasm_bindgen]
     pub fn start_video_analysis(&mut self) {
-        self.video_analyzer = Some(VideoAnalyzer::new(self.config.clone()));
+        self.video_analyzer = Some(
+            VideoAnalyzer::new(self.config.clone()).with_video_config(self.video_config.clone())
+        );
     }
     
-    /// Add a video frame to the analysis
+    /// Start (or restart) live tracking: every `add_video_frame` then
+    /// returns a smoothed per-frame key (also readable with `get_live_key`)
+    #[wasm_bindgen]
+    pub fn start_live_tracking(&mut self) {
+        self.tracker = Some(KeyTracker::new(self.tracking_config.clone()));
+    }
+
+    #[wasm_bindgen]
+    pub fn stop_live_tracking(&mut self) {
+        self.tracker = None;
+    }
+
+    /// Add a video frame to the analysis and/or live tracker
+    /// timestamp: optional frame time (e.g. seconds), reported by `get_video_frames`
+    /// Returns the smoothed key while live tracking, otherwise this frame's
+    /// own key; null if there is none (or no analysis or tracking is running).
+    /// Throws a `DetectError` if the buffer doesn't match the dimensions.
     #[wasm_bindgen]
     pub fn add_video_frame(
         &mut self,
         pixels: &[u8],
         width: u32,
         height: u32,
-    ) -> bool {
-        if let Some(analyzer) = &mut self.video_analyzer {
-            if let Some(result) = detect_chromakey(pixels, width, height, &self.config) {
-                analyzer.add_frame_result(result);
-                return true;
-            }
+        timestamp: Option<f64>,
+    ) -> Result<JsValue, JsValue> {
+        if self.video_analyzer.is_none() && self.tracker.is_none() {
+            return Ok(JsValue::NULL);
         }
-        false
+
+        let image = ImageView::rgba(pixels, width, height).map_err(to_js_error)?;
+        let result = match &mut self.video_analyzer {
+            Some(analyzer) => match timestamp {
+                Some(timestamp) => analyzer.add_frame_at(&image, timestamp).ok(),
+                None => analyzer.add_frame(&image).ok(),
+            },
+            None => detect_chromakey(pixels, width, height, &self.config).ok(),
+        };
+        let key = match &mut self.tracker {
+            Some(tracker) => tracker.update(result.as_ref()),
+            None => result,
+        };
+        key_to_js(key)
+    }
+
+    /// Smoothed key after the latest `add_video_frame`, or null
+    #[wasm_bindgen]
+    pub fn get_live_key(&self) -> Result<JsValue, JsValue> {
+        key_to_js(self.tracker.as_ref().and_then(KeyTracker::current))
     }
     
     /// Get consensus result from all analyzed frames
@@ -79,11 +247,140 @@ impl ChromaDetect {
         }
     }
     
-    /// Update detection configuration
+    /// Segments between scene cuts, each with its own key, frame range and confidence
+    #[wasm_bindgen]
+    pub fn get_video_timeline(&self) -> Result<JsValue, JsValue> {
+        let timeline = self.video_analyzer.as_ref().map(VideoAnalyzer::timeline).unwrap_or_default();
+        Ok(serde_wasm_bindgen::to_value(&timeline)?)
+    }
+    
+    /// Per-frame results of the current analysis: `{ index, timestamp, segment,
+    /// status: "accepted" | "low_quality" | "low_confidence" | "outlier" | "no_key",
+    /// result, error, quality: { sharpness, exposure, grayscale, score } }`
+    #[wasm_bindgen]
+    pub fn get_video_frames(&self) -> Result<JsValue, JsValue> {
+        let frames = self.video_analyzer.as_ref().map(VideoAnalyzer::frames).unwrap_or_default();
+        Ok(serde_wasm_bindgen::to_value(&frames)?)
+    }
+
+    /// Take one frame (by its `index`) back out of the analysis
+    /// Returns false if the frame is unknown or no longer in the history
+    #[wasm_bindgen]
+    pub fn remove_video_frame(&mut self, index: u32) -> bool {
+        self.video_analyzer.as_mut().is_some_and(|analyzer| analyzer.remove_frame(index))
+    }
+
+    /// Save the video analysis (config, statistics and frame history) as a
+    /// versioned JSON string, e.g. to resume after a reload; null without a session
+    #[wasm_bindgen]
+    pub fn save_video_session(&self) -> Option<String> {
+        self.video_analyzer.as_ref().map(VideoAnalyzer::to_json)
+    }
+
+    /// Replace the current video analysis with a saved one
+    /// Throws a `SessionError` if the session can't be read
+    #[wasm_bindgen]
+    pub fn restore_video_session(&mut self, session: &str) -> Result<(), JsValue> {
+        let analyzer = VideoAnalyzer::from_json(session).map_err(session_error_to_js)?;
+        self.video_analyzer = Some(analyzer);
+        Ok(())
+    }
+
+    /// Append a saved session covering the frames after the current ones
+    /// (e.g. a chunk analyzed by another worker); starts from it if no
+    /// analysis is running. Both sessions need the same configuration.
     #[wasm_bindgen]
-    pub fn set_config(&mut self, config: JsValue) {
-        if let Ok(config) = serde_wasm_bindgen::from_value(config) {
-            self.config = config;
+    pub fn merge_video_session(&mut self, session: &str) -> Result<(), JsValue> {
+        let other = VideoAnalyzer::from_json(session).map_err(session_error_to_js)?;
+        match &mut self.video_analyzer {
+            Some(analyzer) => analyzer.merge(other).map_err(session_error_to_js)?,
+            None => self.video_analyzer = Some(other),
         }
+        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Drop every analyzed frame, keeping the session open
+    #[wasm_bindgen]
+    pub fn reset_video_analysis(&mut self) {
+        if let Some(analyzer) = &mut self.video_analyzer {
+            analyzer.reset();
+        }
+    }
+
+    /// Update detection configuration
+    #[wasm_bindgen]
+    pub fn set_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: DetectionConfig = config_from_js(config)?;
+        config.validate().map_err(to_js_error)?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Update matte tolerance/softness
+    #[wasm_bindgen]
+    pub fn set_matte_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: MatteConfig = config_from_js(config)?;
+        config.validate().map_err(to_js_error)?;
+        self.matte_config = config;
+        Ok(())
+    }
+
+    /// Update scene-cut sensitivity; applies from the next `start_video_analysis`
+    #[wasm_bindgen]
+    pub fn set_video_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: VideoConfig = config_from_js(config)?;
+        config.validate().map_err(to_js_error)?;
+        self.video_config = config;
+        Ok(())
+    }
+
+    /// Update live tracking smoothing/hysteresis; applies from the next `start_live_tracking`
+    #[wasm_bindgen]
+    pub fn set_tracking_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: TrackingConfig = config_from_js(config)?;
+        config.validate().map_err(to_js_error)?;
+        self.tracking_config = config;
+        Ok(())
+    }
+
+    /// Update despill algorithm/strength
+    #[wasm_bindgen]
+    pub fn set_despill_config(&mut self, config: JsValue) -> Result<(), JsValue> {
+        let config: DespillConfig = config_from_js(config)?;
+        config.validate().map_err(to_js_error)?;
+        self.despill_config = config;
+        Ok(())
+    }
+}
+
+/// Convert a detection failure into a JS `Error` named `DetectError`,
+/// carrying `kind` plus the variant's fields
+fn to_js_error(err: DetectError) -> JsValue {
+    named_js_error("DetectError", &err)
+}
+
+fn session_error_to_js(err: SessionError) -> JsValue {
+    named_js_error("SessionError", &err)
+}
+
+fn named_js_error<E: std::fmt::Display + Serialize>(name: &str, err: &E) -> JsValue {
+    let js_err = js_sys::Error::new(&err.to_string());
+    js_err.set_name(name);
+    if let Ok(details) = serde_wasm_bindgen::to_value(err) {
+        js_sys::Object::assign(&js_err, details.unchecked_ref());
+    }
+    js_err.into()
+}
+
+/// A key as a JS object, or null
+fn key_to_js(key: Option<ChromakeyResult>) -> Result<JsValue, JsValue> {
+    match key {
+        Some(key) => Ok(serde_wasm_bindgen::to_value(&key)?),
+        None => Ok(JsValue::NULL),
+    }
+}
+
+fn config_from_js<T: DeserializeOwned>(config: JsValue) -> Result<T, JsValue> {
+    serde_wasm_bindgen::from_value(config)
+        .map_err(|e| to_js_error(DetectError::InvalidConfig { message: e.to_string() }))
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use crate::color::{RGB, HSV, angular_distance};
+use crate::detection::{ChromakeyResult, DetectError, check_range};
+use crate::pixel_format::ImageView;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatteConfig {
+    pub tolerance: f32,    // Default: 0.15 (distance fully keyed out)
+    pub softness: f32,     // Default: 0.1 (width of the soft edge ramp)
+}
+
+impl Default for MatteConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.15,
+            softness: 0.1,
+        }
+    }
+}
+
+impl MatteConfig {
+    /// Reject out-of-range (or NaN) fields; key distances run 0 - 1
+    pub fn validate(&self) -> Result<(), DetectError> {
+        check_range("tolerance", self.tolerance, 0.0, 1.0)?;
+        check_range("softness", self.softness, 0.0, 1.0)?;
+        Ok(())
+    }
+}
+
+/// Build a per-pixel alpha matte for an RGBA buffer.
+/// Returns one byte per pixel: 0 = key color (transparent), 255 = foreground.
+pub fn generate_matte(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    key: &ChromakeyResult,
+    config: &MatteConfig,
+) -> Result<Vec<u8>, DetectError> {
+    ImageView::rgba(pixels, width, height)?;
+    let pixel_count = width as usize * height as usize;
+    let key_hsv = key.color.to_hsv();
+
+    let mut matte = Vec::with_capacity(pixel_count);
+    for i in 0..pixel_count {
+        let idx = i * 4;
+        let hsv = RGB {
+            r: pixels[idx],
+            g: pixels[idx + 1],
+            b: pixels[idx + 2],
+        }.to_hsv();
+
+        let distance = key_distance(&hsv, &key_hsv);
+        matte.push((alpha_for_distance(distance, config) * 255.0).round() as u8);
+    }
+
+    Ok(matte)
+}
+
+fn key_distance(pixel: &HSV, key: &HSV) -> f32 {
+    let s_dist = (pixel.s - key.s).abs();
+    let v_dist = (pixel.v - key.v).abs();
+
+    // Grayscale keys (black/white backdrops) have no meaningful hue
+    if key.s < 0.15 {
+        return s_dist * 0.5 + v_dist * 0.5;
+    }
+
+    // Same weighting as clustering: hue dominates, brightness matters least
+    // so shadows on the backdrop still key out
+    let h_dist = angular_distance(pixel.h, key.h) / 180.0;
+    h_dist * 0.6 + s_dist * 0.3 + v_dist * 0.1
+}
+
+fn alpha_for_distance(distance: f32, config: &MatteConfig) -> f32 {
+    if distance <= config.tolerance {
+        return 0.0;
+    }
+    if config.softness <= 0.0 {
+        return 1.0;
+    }
+
+    // Smoothstep across the softness band for a clean edge falloff
+    let t = ((distance - config.tolerance) / config.softness).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection::DetectionMethod;
+
+    fn green_key() -> ChromakeyResult {
+        ChromakeyResult {
+            color: RGB { r: 0, g: 255, b: 0 },
+            confidence: 0.9,
+            coverage: 0.5,
+            hue: 120.0,
+            method_used: DetectionMethod::Hybrid,
+        }
+    }
+
+    #[test]
+    fn test_matte_keys_out_backdrop() {
+        let pixels = vec![
+            0, 255, 0, 255,     // Green backdrop
+            20, 230, 30, 255,   // Slightly off green
+            230, 160, 120, 255, // Skin tone
+            50, 50, 180, 255,   // Blue shirt
+        ];
+
+        let matte = generate_matte(&pixels, 4, 1, &green_key(), &MatteConfig::default()).unwrap();
+
+        assert_eq!(matte.len(), 4);
+        assert_eq!(matte[0], 0);
+        assert_eq!(matte[1], 0);
+        assert_eq!(matte[2], 255);
+        assert_eq!(matte[3], 255);
+    }
+
+    #[test]
+    fn test_matte_softness_ramp() {
+        let config = MatteConfig { tolerance: 0.1, softness: 0.2 };
+
+        assert_eq!(alpha_for_distance(0.05, &config), 0.0);
+        assert_eq!(alpha_for_distance(0.5, &config), 1.0);
+
+        let mid = alpha_for_distance(0.2, &config);
+        assert!(mid > 0.0 && mid < 1.0, "Soft edge should be partial, got {}", mid);
+
+        // Zero softness gives a hard matte
+        let hard = MatteConfig { tolerance: 0.1, softness: 0.0 };
+        assert_eq!(alpha_for_distance(0.11, &hard), 1.0);
+    }
+
+    #[test]
+    fn test_matte_config_validation() {
+        assert!(MatteConfig::default().validate().is_ok());
+        for tolerance in [f32::NAN, -0.1, 1.5] {
+            let config = MatteConfig { tolerance, ..MatteConfig::default() };
+            assert!(matches!(
+                config.validate(),
+                Err(DetectError::ConfigOutOfRange { field, .. }) if field == "tolerance"
+            ));
+        }
+        let config = MatteConfig { softness: f32::NAN, ..MatteConfig::default() };
+        assert!(matches!(
+            config.validate(),
+            Err(DetectError::ConfigOutOfRange { field, .. }) if field == "softness"
+        ));
+    }
+
+    #[test]
+    fn test_matte_grayscale_key() {
+        let black_key = ChromakeyResult {
+            color: RGB { r: 5, g: 5, b: 5 },
+            confidence: 0.9,
+            coverage: 0.5,
+            hue: 0.0,
+            method_used: DetectionMethod::Hybrid,
+        };
+        let pixels = vec![
+            0, 0, 0, 255,       // Black backdrop
+            255, 0, 0, 255,     // Red subject (hue matches the gray key's 0°)
+        ];
+
+        let matte = generate_matte(&pixels, 2, 1, &black_key, &MatteConfig::default()).unwrap();
+
+        assert_eq!(matte[0], 0);
+        assert_eq!(matte[1], 255);
+    }
+
+    #[test]
+    fn test_matte_rejects_short_buffer() {
+        let pixels = [0, 255, 0, 255];
+        assert_eq!(
+            generate_matte(&pixels, 2, 1, &green_key(), &MatteConfig::default()),
+            Err(DetectError::BufferTooShort { expected: 8, actual: 4 })
+        );
+        assert_eq!(
+            generate_matte(&pixels, 0, 1, &green_key(), &MatteConfig::default()),
+            Err(DetectError::InvalidDimensions { width: 0, height: 1 })
+        );
+    }
+}
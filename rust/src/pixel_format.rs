@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use crate::color::RGB;
+use crate::detection::DetectError;
+
+/// Channel order of an interleaved 8-bit pixel buffer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    #[default]
+    Rgba,           // Canvas ImageData
+    Bgra,           // Most native video decoders
+    Argb,
+    Rgb24,
+    Bgr24,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Bgra | PixelFormat::Argb => 4,
+            PixelFormat::Rgb24 | PixelFormat::Bgr24 => 3,
+        }
+    }
+
+    /// Byte offsets of the red, green and blue channels within a pixel
+    fn rgb_offsets(&self) -> (usize, usize, usize) {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Rgb24 => (0, 1, 2),
+            PixelFormat::Bgra | PixelFormat::Bgr24 => (2, 1, 0),
+            PixelFormat::Argb => (1, 2, 3),
+        }
+    }
+
+    /// Byte offset of the alpha channel, if the format has one
+    pub fn alpha_offset(&self) -> Option<usize> {
+        match self {
+            PixelFormat::Rgba | PixelFormat::Bgra => Some(3),
+            PixelFormat::Argb => Some(0),
+            PixelFormat::Rgb24 | PixelFormat::Bgr24 => None,
+        }
+    }
+}
+
+/// How pixels are laid out in memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PixelLayout {
+    pub format: PixelFormat,
+    #[serde(alias = "rowStride")]
+    pub row_stride: usize,          // Bytes per row including padding; 0 = tightly packed
+}
+
+impl PixelLayout {
+    pub fn new(format: PixelFormat, row_stride: usize) -> Self {
+        Self { format, row_stride }
+    }
+
+    /// Tightly packed RGBA, as produced by a canvas
+    pub fn rgba() -> Self {
+        Self::default()
+    }
+}
+
+/// Anything the detection stages can sample colors from
+pub trait PixelSource {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+
+    /// Color at (x, y); coordinates must be inside the image
+    fn pixel(&self, x: u32, y: u32) -> RGB;
+
+    /// Alpha at (x, y); opaque for sources without an alpha channel
+    fn alpha(&self, _x: u32, _y: u32) -> u8 {
+        255
+    }
+
+    fn pixel_count(&self) -> usize {
+        self.width() as usize * self.height() as usize
+    }
+}
+
+/// A validated, read-only view over a pixel buffer in any supported layout
+#[derive(Clone, Copy)]
+pub struct ImageView<'a> {
+    pixels: &'a [u8],
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    stride: usize,
+    offsets: (usize, usize, usize),
+    alpha_offset: Option<usize>,
+}
+
+impl<'a> ImageView<'a> {
+    pub fn new(pixels: &'a [u8], width: u32, height: u32, layout: PixelLayout) -> Result<Self, DetectError> {
+        if width == 0 || height == 0 {
+            return Err(DetectError::InvalidDimensions { width, height });
+        }
+
+        let bpp = layout.format.bytes_per_pixel();
+        let row_bytes = (width as usize)
+            .checked_mul(bpp)
+            .ok_or(DetectError::InvalidDimensions { width, height })?;
+        let stride = if layout.row_stride == 0 { row_bytes } else { layout.row_stride };
+        if stride < row_bytes {
+            return Err(DetectError::InvalidRowStride { row_stride: stride, min: row_bytes });
+        }
+
+        // The last row doesn't need its padding
+        let expected = stride
+            .checked_mul(height as usize - 1)
+            .and_then(|bytes| bytes.checked_add(row_bytes))
+            .ok_or(DetectError::InvalidDimensions { width, height })?;
+        if pixels.len() < expected {
+            return Err(DetectError::BufferTooShort { expected, actual: pixels.len() });
+        }
+
+        Ok(Self {
+            pixels,
+            width,
+            height,
+            format: layout.format,
+            stride,
+            offsets: layout.format.rgb_offsets(),
+            alpha_offset: layout.format.alpha_offset(),
+        })
+    }
+
+    /// Tightly packed RGBA view
+    pub fn rgba(pixels: &'a [u8], width: u32, height: u32) -> Result<Self, DetectError> {
+        Self::new(pixels, width, height, PixelLayout::rgba())
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+}
+
+impl PixelSource for ImageView<'_> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    fn pixel(&self, x: u32, y: u32) -> RGB {
+        let idx = y as usize * self.stride + x as usize * self.format.bytes_per_pixel();
+        let (r, g, b) = self.offsets;
+        RGB {
+            r: self.pixels[idx + r],
+            g: self.pixels[idx + g],
+            b: self.pixels[idx + b],
+        }
+    }
+
+    #[inline]
+    fn alpha(&self, x: u32, y: u32) -> u8 {
+        match self.alpha_offset {
+            Some(offset) => {
+                self.pixels[y as usize * self.stride + x as usize * self.format.bytes_per_pixel() + offset]
+            }
+            None => 255,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_orders() {
+        let cases = [
+            (PixelFormat::Rgba, vec![10, 20, 30, 255]),
+            (PixelFormat::Bgra, vec![30, 20, 10, 255]),
+            (PixelFormat::Argb, vec![255, 10, 20, 30]),
+            (PixelFormat::Rgb24, vec![10, 20, 30]),
+            (PixelFormat::Bgr24, vec![30, 20, 10]),
+        ];
+
+        for (format, pixels) in cases {
+            let view = ImageView::new(&pixels, 1, 1, PixelLayout::new(format, 0)).unwrap();
+            let rgb = view.pixel(0, 0);
+            assert_eq!((rgb.r, rgb.g, rgb.b), (10, 20, 30), "{:?}", format);
+            assert_eq!(view.alpha(0, 0), 255, "{:?}", format);
+        }
+
+        let argb = [128, 10, 20, 30];
+        let view = ImageView::new(&argb, 1, 1, PixelLayout::new(PixelFormat::Argb, 0)).unwrap();
+        assert_eq!(view.alpha(0, 0), 128);
+    }
+
+    #[test]
+    fn test_padded_rows() {
+        // 2x2 BGRA with 4 bytes of padding per row
+        let pixels = vec![
+            0, 0, 255, 255,   0, 255, 0, 255,   9, 9, 9, 9,
+            255, 0, 0, 255,   1, 2, 3, 255,
+        ];
+        let layout = PixelLayout::new(PixelFormat::Bgra, 12);
+        let view = ImageView::new(&pixels, 2, 2, layout).unwrap();
+
+        assert_eq!(view.pixel(0, 0).r, 255);
+        assert_eq!(view.pixel(1, 0).g, 255);
+        assert_eq!(view.pixel(0, 1).b, 255);
+        let last = view.pixel(1, 1);
+        assert_eq!((last.r, last.g, last.b), (3, 2, 1));
+    }
+
+    #[test]
+    fn test_layout_validation() {
+        let pixels = vec![0u8; 24];
+
+        assert_eq!(
+            ImageView::new(&pixels, 4, 2, PixelLayout::new(PixelFormat::Rgb24, 8)).err(),
+            Some(DetectError::InvalidRowStride { row_stride: 8, min: 12 })
+        );
+        assert_eq!(
+            ImageView::new(&pixels, 4, 2, PixelLayout::rgba()).err(),
+            Some(DetectError::BufferTooShort { expected: 32, actual: 24 })
+        );
+        assert!(ImageView::new(&pixels, 4, 2, PixelLayout::new(PixelFormat::Rgb24, 0)).is_ok());
+    }
+}
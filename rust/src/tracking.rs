@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use crate::color::{RGB, angular_distance};
+use crate::detection::{ChromakeyResult, DetectError, DetectionMethod, check_range};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackingConfig {
+    pub smoothing: f32,         // Default: 0.3 (weight of each new frame in the running key)
+    #[serde(alias = "switchHueThreshold")]
+    pub switch_hue_threshold: f32, // Default: 30.0 (degrees; further away counts as a different key)
+    #[serde(alias = "switchFrames")]
+    pub switch_frames: u32,     // Default: 3 (consecutive frames before switching to a new key)
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            smoothing: 0.3,
+            switch_hue_threshold: 30.0,
+            switch_frames: 3,
+        }
+    }
+}
+
+impl TrackingConfig {
+    /// Reject out-of-range (or NaN) fields; a NaN smoothing rate would
+    /// poison the tracked key for good
+    pub fn validate(&self) -> Result<(), DetectError> {
+        check_range("smoothing", self.smoothing, 0.0, 1.0)?;
+        check_range("switch_hue_threshold", self.switch_hue_threshold, 0.0, 180.0)?;
+        Ok(())
+    }
+}
+
+/// Exponentially smoothed key; color channels kept as floats so small
+/// per-frame changes aren't lost to rounding
+#[derive(Clone, Copy, Debug)]
+struct SmoothedKey {
+    r: f32,
+    g: f32,
+    b: f32,
+    hue: f32,
+    confidence: f32,
+    coverage: f32,
+}
+
+impl SmoothedKey {
+    fn from_result(result: &ChromakeyResult) -> Self {
+        Self {
+            r: result.color.r as f32,
+            g: result.color.g as f32,
+            b: result.color.b as f32,
+            hue: result.hue,
+            confidence: result.confidence,
+            coverage: result.coverage,
+        }
+    }
+
+    fn blend(&mut self, result: &ChromakeyResult, rate: f32) {
+        let lerp = |from: f32, to: f32| from + (to - from) * rate;
+        self.r = lerp(self.r, result.color.r as f32);
+        self.g = lerp(self.g, result.color.g as f32);
+        self.b = lerp(self.b, result.color.b as f32);
+        // Along the shorter arc so a red key doesn't swing through cyan
+        let hue_diff = (result.hue - self.hue + 180.0).rem_euclid(360.0) - 180.0;
+        self.hue = (self.hue + hue_diff * rate).rem_euclid(360.0);
+        self.confidence = lerp(self.confidence, result.confidence);
+        self.coverage = lerp(self.coverage, result.coverage);
+    }
+
+    fn to_result(self) -> ChromakeyResult {
+        ChromakeyResult {
+            color: RGB {
+                r: self.r.round() as u8,
+                g: self.g.round() as u8,
+                b: self.b.round() as u8,
+            },
+            confidence: self.confidence,
+            coverage: self.coverage,
+            hue: self.hue,
+            method_used: DetectionMethod::Hybrid,
+        }
+    }
+}
+
+/// Per-frame key for live video: smooths hue and color across frames and
+/// only switches to a different key once it has persisted for
+/// `switch_frames` consecutive frames, so one outlier can't flip it
+pub struct KeyTracker {
+    config: TrackingConfig,
+    current: Option<SmoothedKey>,
+    pending: Option<(SmoothedKey, u32)>,    // Challenger key and its streak length
+}
+
+impl KeyTracker {
+    pub fn new(config: TrackingConfig) -> Self {
+        Self {
+            config,
+            current: None,
+            pending: None,
+        }
+    }
+
+    /// Feed one frame's detection (None if detection failed) and get the
+    /// smoothed key to use for it
+    pub fn update(&mut self, frame: Option<&ChromakeyResult>) -> Option<ChromakeyResult> {
+        let rate = self.config.smoothing.clamp(0.0, 1.0);
+
+        let Some(frame) = frame else {
+            // Missed frame: keep the key, but trust it a little less
+            if let Some(current) = &mut self.current {
+                current.confidence *= 1.0 - rate;
+            }
+            return self.current();
+        };
+
+        let Some(current) = &mut self.current else {
+            self.current = Some(SmoothedKey::from_result(frame));
+            return self.current();
+        };
+
+        if angular_distance(frame.hue, current.hue) < self.config.switch_hue_threshold {
+            current.blend(frame, rate);
+            self.pending = None;
+            return self.current();
+        }
+
+        // A different key: track it as a challenger until it has held long enough
+        let streak = match &mut self.pending {
+            Some((pending, count)) if angular_distance(frame.hue, pending.hue) < self.config.switch_hue_threshold => {
+                pending.blend(frame, rate);
+                *count += 1;
+                *count
+            }
+            _ => {
+                self.pending = Some((SmoothedKey::from_result(frame), 1));
+                1
+            }
+        };
+
+        if streak >= self.config.switch_frames {
+            self.current = self.pending.take().map(|(pending, _)| pending);
+        }
+        self.current()
+    }
+
+    /// The key as of the last update
+    pub fn current(&self) -> Option<ChromakeyResult> {
+        self.current.map(SmoothedKey::to_result)
+    }
+
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(hue: f32, color: RGB) -> ChromakeyResult {
+        ChromakeyResult {
+            color,
+            confidence: 0.9,
+            coverage: 0.5,
+            hue,
+            method_used: DetectionMethod::Edge,
+        }
+    }
+
+    fn green(hue: f32) -> ChromakeyResult {
+        frame(hue, RGB { r: 0, g: 255, b: 0 })
+    }
+
+    fn blue() -> ChromakeyResult {
+        frame(240.0, RGB { r: 0, g: 0, b: 255 })
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(TrackingConfig::default().validate().is_ok());
+
+        for smoothing in [f32::NAN, -0.1, 1.5] {
+            let config = TrackingConfig { smoothing, ..TrackingConfig::default() };
+            assert!(matches!(
+                config.validate(),
+                Err(DetectError::ConfigOutOfRange { field, .. }) if field == "smoothing"
+            ));
+        }
+        for switch_hue_threshold in [f32::NAN, -5.0, 200.0] {
+            let config = TrackingConfig { switch_hue_threshold, ..TrackingConfig::default() };
+            assert!(matches!(
+                config.validate(),
+                Err(DetectError::ConfigOutOfRange { field, .. }) if field == "switch_hue_threshold"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_smooths_jitter() {
+        let mut tracker = KeyTracker::new(TrackingConfig::default());
+
+        assert_eq!(tracker.update(Some(&green(120.0))).unwrap().hue, 120.0);
+        let smoothed = tracker.update(Some(&green(130.0))).unwrap();
+        // 0.3 of the way from 120 to 130
+        assert!((smoothed.hue - 123.0).abs() < 0.01, "hue = {}", smoothed.hue);
+    }
+
+    #[test]
+    fn test_single_outlier_ignored() {
+        let mut tracker = KeyTracker::new(TrackingConfig::default());
+        for _ in 0..5 {
+            tracker.update(Some(&green(120.0)));
+        }
+
+        let after_outlier = tracker.update(Some(&blue())).unwrap();
+        assert_eq!(after_outlier.hue, 120.0);
+
+        // Back to green resets the challenger's streak
+        tracker.update(Some(&green(120.0)));
+        tracker.update(Some(&blue()));
+        tracker.update(Some(&blue()));
+        assert_eq!(tracker.current().unwrap().hue, 120.0);
+    }
+
+    #[test]
+    fn test_persistent_change_switches() {
+        let mut tracker = KeyTracker::new(TrackingConfig::default());
+        for _ in 0..5 {
+            tracker.update(Some(&green(120.0)));
+        }
+
+        tracker.update(Some(&blue()));
+        tracker.update(Some(&blue()));
+        let switched = tracker.update(Some(&blue())).unwrap();
+        assert_eq!(switched.hue, 240.0);
+        assert_eq!(switched.color.b, 255);
+    }
+
+    #[test]
+    fn test_red_wraps_and_missed_frames() {
+        let mut tracker = KeyTracker::new(TrackingConfig::default());
+        let red = |hue| frame(hue, RGB { r: 255, g: 0, b: 0 });
+
+        tracker.update(Some(&red(355.0)));
+        let smoothed = tracker.update(Some(&red(5.0))).unwrap();
+        assert!((smoothed.hue - 358.0).abs() < 0.01, "hue = {}", smoothed.hue);
+
+        // A dropped detection keeps the key but lowers confidence
+        let held = tracker.update(None).unwrap();
+        assert!((held.hue - 358.0).abs() < 0.01);
+        assert!(held.confidence < 0.9);
+
+        tracker.reset();
+        assert!(tracker.update(None).is_none());
+    }
+}
@@ -1,122 +1,951 @@
-use crate::color::RGB;
-use crate::detection::{ChromakeyResult, DetectionConfig, DetectionMethod};
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::color::{RGB, CircularStats, YCbCrMatrix};
+use crate::detection::{ChromakeyResult, DetectError, DetectionConfig, DetectionMethod, check_range, detect_in_image};
+use crate::pixel_format::PixelSource;
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoConfig {
+    #[serde(alias = "sceneCutThreshold")]
+    pub scene_cut_threshold: f32,   // Default: 0.5 (histogram distance 0 - 1 that starts a new segment)
+    #[serde(alias = "frameHistory")]
+    pub frame_history: usize,       // Default: 1000 (latest frames kept for frames() / remove_frame)
+    #[serde(alias = "minFrameQuality")]
+    pub min_frame_quality: f32,     // Default: 0.2 (frames scoring lower only vote when none score higher; 0 keeps all)
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        Self {
+            scene_cut_threshold: 0.5,
+            frame_history: 1000,
+            min_frame_quality: 0.2,
+        }
+    }
+}
+
+impl VideoConfig {
+    /// Reject out-of-range (or NaN) fields before a session uses them
+    pub fn validate(&self) -> Result<(), DetectError> {
+        check_range("scene_cut_threshold", self.scene_cut_threshold, 0.0, 1.0)?;
+        check_range("min_frame_quality", self.min_frame_quality, 0.0, 1.0)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct VideoAnalyzer {
-    // config is stored but currently unused in the logic below, keeping it for future use or matching plan
-    #[allow(dead_code)] 
     config: DetectionConfig,
-    frame_results: Vec<ChromakeyResult>,
+    video_config: VideoConfig,
+    overall: KeyVotes,
+    segments: Vec<Segment>,
+    first_signature: Option<FrameSignature>,   // For joining segments when merging sessions
+    last_signature: Option<FrameSignature>,
+    frame_count: u32,
+    next_index: u32,
+    history: VecDeque<FrameRecord>,
 }
 
-impl VideoAnalyzer {
-    pub fn new(config: DetectionConfig) -> Self {
-        Self {
-            config,
-            frame_results: Vec::new(),
+/// A run of frames between scene cuts
+#[derive(Clone, Debug, Serialize)]
+pub struct VideoSegment {
+    pub start_frame: u32,
+    pub end_frame: u32,                 // Inclusive
+    pub start_time: Option<f64>,        // Timestamps of the first/last frame, if given
+    pub end_time: Option<f64>,
+    pub key: Option<ChromakeyResult>,   // None if no frame in the segment had a key
+    pub confidence: f32,                // Key confidence, 0 without a key
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Segment {
+    start_frame: u32,
+    end_frame: u32,
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+    votes: KeyVotes,
+}
+
+/// Layout version written by `VideoAnalyzer::to_json`
+pub const SESSION_VERSION: u32 = 1;
+
+/// Why a saved session could not be restored or merged
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionError {
+    Malformed { message: String },
+    UnsupportedVersion { version: u32, supported: u32 },
+    ConfigMismatch,                 // Sessions analyzed with different settings can't be merged
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Malformed { message } => write!(f, "malformed video session: {}", message),
+            SessionError::UnsupportedVersion { version, supported } => {
+                write!(f, "video session version {} is not supported (expected {})", version, supported)
+            }
+            SessionError::ConfigMismatch => {
+                write!(f, "video sessions were analyzed with different configurations")
+            }
         }
     }
-    
-    pub fn add_frame_result(&mut self, result: ChromakeyResult) {
-        self.frame_results.push(result);
+}
+
+impl std::error::Error for SessionError {}
+
+#[derive(Serialize)]
+struct SavedSession<'a> {
+    version: u32,
+    analyzer: &'a VideoAnalyzer,
+}
+
+#[derive(Deserialize)]
+struct SessionHeader {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct RestoredSession {
+    analyzer: VideoAnalyzer,
+}
+
+/// What became of one frame in the consensus
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameStatus {
+    Accepted,           // Votes for its segment's key
+    LowQuality,         // Blurred, badly exposed or colorless while other frames weren't; see `quality`
+    LowConfidence,      // Below confidence_threshold while other frames cleared it
+    Outlier,            // Too far from the segment's consensus hue
+    NoKey,              // Detection failed; see `error`
+}
+
+/// One analyzed frame, as reported by `VideoAnalyzer::frames`
+#[derive(Clone, Debug, Serialize)]
+pub struct FrameReport {
+    pub index: u32,                     // Position in the session, never reused
+    pub timestamp: Option<f64>,         // As passed to add_frame_at
+    pub segment: usize,                 // Index into timeline()
+    pub status: FrameStatus,
+    pub result: Option<ChromakeyResult>,
+    pub error: Option<DetectError>,     // Why detection failed, for NoKey frames
+    pub quality: Option<FrameQuality>,  // None for frames added as results
+}
+
+#[derive(Serialize, Deserialize)]
+struct FrameRecord {
+    index: u32,
+    timestamp: Option<f64>,
+    segment: usize,
+    outcome: Result<ChromakeyResult, DetectError>,
+    quality: Option<FrameQuality>,
+}
+
+impl FrameRecord {
+    /// The key this frame votes with, its vote weight and the pool it
+    /// votes in, if it has a key at all
+    fn vote(&self, min_quality: f32, confidence_threshold: f32) -> Option<(&ChromakeyResult, f32, VotePool)> {
+        let result = self.outcome.as_ref().ok()?;
+        let score = self.quality.map_or(1.0, |quality| quality.score);
+        let pool = if score < min_quality {
+            VotePool::LowQuality
+        } else if result.confidence >= confidence_threshold {
+            VotePool::Confident
+        } else {
+            VotePool::Weak
+        };
+        Some((result, score, pool))
     }
-    
-    pub fn compute_consensus(&self) -> Option<ChromakeyResult> {
-        if self.frame_results.is_empty() {
-            return None;
+}
+
+// Frames are reduced to this many samples per axis for scene signatures
+const SIGNATURE_GRID: u32 = 64;
+const SIGNATURE_HUE_BINS: usize = 16;
+const SIGNATURE_GRAY_BINS: usize = 4;
+
+/// Coarse color distribution of a frame: 16 hue bins plus 4 brightness bins
+/// for grayscale pixels, normalized to sum to 1
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameSignature {
+    bins: [f32; SIGNATURE_HUE_BINS + SIGNATURE_GRAY_BINS],
+}
+
+impl FrameSignature {
+    pub fn from_image<S: PixelSource>(image: &S, grayscale_saturation: f32) -> Self {
+        let mut bins = [0.0f32; SIGNATURE_HUE_BINS + SIGNATURE_GRAY_BINS];
+        let step_x = (image.width() / SIGNATURE_GRID).max(1);
+        let step_y = (image.height() / SIGNATURE_GRID).max(1);
+
+        let mut total = 0.0;
+        for y in (0..image.height()).step_by(step_y as usize) {
+            for x in (0..image.width()).step_by(step_x as usize) {
+                let hsv = image.pixel(x, y).to_hsv();
+                let index = if hsv.s < grayscale_saturation {
+                    SIGNATURE_HUE_BINS + ((hsv.v * SIGNATURE_GRAY_BINS as f32) as usize).min(SIGNATURE_GRAY_BINS - 1)
+                } else {
+                    ((hsv.h / 360.0 * SIGNATURE_HUE_BINS as f32) as usize).min(SIGNATURE_HUE_BINS - 1)
+                };
+                bins[index] += 1.0;
+                total += 1.0;
+            }
         }
-        
-        // Strategy: Find the most consistent color across frames
-        // 1. Group similar colors (hue within ±10°)
-        // 2. Find largest group
-        // 3. Average properties within group
-        // 4. Return high-confidence result
-        
-        let groups = self.group_similar_colors();
-        let largest_group = groups.iter().max_by_key(|g| g.len())?;
-        
-        // Average the results in the largest group
-        let consensus = self.average_results(largest_group);
-        
-        // Calculate consensus confidence: base confidence * agreement factor
-        // agreement factor is essentially what % of frames agreed on this color
-        let agreement_percentage = largest_group.len() as f32 / self.frame_results.len() as f32;
-        
-        Some(ChromakeyResult {
-            color: consensus.color,
-            confidence: consensus.confidence * agreement_percentage,
-            coverage: consensus.coverage,
-            hue: consensus.hue,
-            method_used: DetectionMethod::Hybrid,
-        })
+
+        if total > 0.0 {
+            bins.iter_mut().for_each(|b| *b /= total);
+        }
+        Self { bins }
     }
-    
-    fn group_similar_colors(&self) -> Vec<Vec<&ChromakeyResult>> {
-        let mut groups: Vec<Vec<&ChromakeyResult>> = Vec::new();
-        let hue_tolerance = 10.0; // ±10 degrees
-        
-        for result in &self.frame_results {
-            let mut added = false;
-            
-            // Try to add to existing group
-            for group in &mut groups {
-                if let Some(first) = group.first() {
-                    let hue_diff = (result.hue - first.hue).abs();
-                    // Handle hue wraparound (0° = 360°)
-                    let hue_diff = hue_diff.min(360.0 - hue_diff);
-                    
-                    if hue_diff < hue_tolerance {
-                        group.push(result);
-                        added = true;
-                        break;
+
+    /// Share of the frame whose color bin changed: 0 = same, 1 = nothing in common
+    pub fn distance(&self, other: &FrameSignature) -> f32 {
+        self.bins.iter().zip(&other.bins).map(|(a, b)| (a - b).abs()).sum::<f32>() / 2.0
+    }
+}
+
+// Quality probes look this many pixels along each axis from a grid sample
+const BLUR_SPAN: u32 = 4;
+// Largest channel change (0 - 1) across a span that counts as an edge worth measuring
+const MIN_EDGE_CONTRAST: f32 = 0.1;
+// Luma outside this range counts as crushed or clipped
+const DARK_LUMA: f32 = 0.06;
+const BRIGHT_LUMA: f32 = 0.96;
+
+/// How usable a frame is for voting, each part 0 - 1
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameQuality {
+    pub sharpness: f32,     // 1 = edges change within a pixel, 0 = smeared over BLUR_SPAN or more
+    pub exposure: f32,      // Share of samples neither crushed to black nor clipped to white
+    pub grayscale: f32,     // Share of samples below grayscale_saturation
+    pub score: f32,         // sharpness * exposure * (1 - grayscale); sharpness alone for grayscale keys
+}
+
+impl FrameQuality {
+    /// Estimate quality from the same sample grid as the scene signature.
+    /// Sharpness compares the steepest one-pixel step inside each edge
+    /// with the edge's total change: a crisp edge makes it in one step,
+    /// a blurred one spreads it out. Frames without edges count as sharp.
+    pub fn from_image<S: PixelSource>(image: &S, grayscale_saturation: f32) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let step_x = (width / SIGNATURE_GRID).max(1);
+        let step_y = (height / SIGNATURE_GRID).max(1);
+
+        let (mut samples, mut exposed, mut gray) = (0u32, 0u32, 0u32);
+        let (mut steepest_sum, mut edge_sum) = (0.0f32, 0.0f32);
+        for y in (0..height).step_by(step_y as usize) {
+            for x in (0..width).step_by(step_x as usize) {
+                let rgb = image.pixel(x, y);
+                let luma = rgb.to_ycbcr(YCbCrMatrix::Bt709).y / 255.0;
+                samples += 1;
+                if (DARK_LUMA..=BRIGHT_LUMA).contains(&luma) {
+                    exposed += 1;
+                }
+                if rgb.to_hsv().s < grayscale_saturation {
+                    gray += 1;
+                }
+
+                // Horizontal and vertical probes
+                let probes: [&dyn Fn(u32) -> RGB; 2] = [&|i| image.pixel(x + i, y), &|i| image.pixel(x, y + i)];
+                let fits = [x + BLUR_SPAN < width, y + BLUR_SPAN < height];
+                for (probe, fits) in probes.into_iter().zip(fits) {
+                    if !fits {
+                        continue;
                     }
+                    let contrast = channel_difference(rgb, probe(BLUR_SPAN));
+                    if contrast < MIN_EDGE_CONTRAST {
+                        continue;
+                    }
+                    let steepest = (0..BLUR_SPAN)
+                        .map(|i| channel_difference(probe(i), probe(i + 1)))
+                        .fold(0.0f32, f32::max);
+                    steepest_sum += steepest.min(contrast);
+                    edge_sum += contrast;
                 }
             }
-            
-            // Create new group if needed
-            if !added {
-                groups.push(vec![result]);
+        }
+
+        // The steepest step is between 1/BLUR_SPAN (linear ramp) and all of the edge
+        let sharpness = if edge_sum > 0.0 {
+            let floor = 1.0 / BLUR_SPAN as f32;
+            ((steepest_sum / edge_sum - floor) / (1.0 - floor)).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let samples = samples.max(1) as f32;
+        let exposure = exposed as f32 / samples;
+        let grayscale = gray as f32 / samples;
+
+        Self {
+            sharpness,
+            exposure,
+            grayscale,
+            score: sharpness * exposure * (1.0 - grayscale),
+        }
+    }
+
+    /// Rescore for the key detected in the frame: with a black, white or
+    /// gray backdrop the dark, clipped and colorless samples are the key
+    /// itself, so only sharpness counts
+    fn for_key(mut self, key: &ChromakeyResult, grayscale_saturation: f32) -> Self {
+        if key.color.to_hsv().s < grayscale_saturation {
+            self.score = self.sharpness;
+        }
+        self
+    }
+}
+
+/// Largest per-channel difference, 0 - 1
+fn channel_difference(a: RGB, b: RGB) -> f32 {
+    let diff = |a: u8, b: u8| a.abs_diff(b);
+    diff(a.r, b.r).max(diff(a.g, b.g)).max(diff(a.b, b.b)) as f32 / 255.0
+}
+
+/// Confidence-split hue votes for one consensus (whole video or one segment)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct KeyVotes {
+    confident: HueBins,     // Frames at or above config.confidence_threshold
+    weak: HueBins,          // The rest; only used when no frame is confident
+    low_quality: HueBins,   // Frames under min_frame_quality; only used when no other frame votes
+}
+
+/// Which `KeyVotes` bins a frame votes in, in order of preference
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VotePool {
+    Confident,
+    Weak,
+    LowQuality,
+}
+
+// Frames within ±10° of the densest hue form the consensus group
+const GROUP_HUE_TOLERANCE: f32 = 10.0;
+// Outlier rejection never looks further than this from the densest hue
+const MAX_GROUP_SPREAD: f32 = 30.0;
+// Frames more than this many (normal-scaled) MADs from the median hue are outliers
+const OUTLIER_MADS: f32 = 3.0;
+// Scales MAD to a standard deviation for normally distributed hues
+const MAD_TO_SIGMA: f32 = 1.4826;
+// Floor on the inlier band so identical frames don't reject their neighbours
+const MIN_INLIER_SPREAD: f32 = 2.0;
+// Even a zero-confidence frame keeps a sliver of weight
+const MIN_FRAME_WEIGHT: f32 = 0.01;
+
+/// Running, confidence-weighted totals for frames in one 1° hue bin.
+/// Everything is a sum, so results don't depend on frame order.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct HueBin {
+    count: u32,
+    weight: f64,            // Sum of frame confidences, scaled by frame quality
+    hue: CircularStats,     // Weighted
+    r_sum: f64,             // Weighted color sums
+    g_sum: f64,
+    b_sum: f64,
+    confidence_sum: f64,    // Unweighted, for the mean confidence
+    coverage_sum: f64,      // Weighted
+}
+
+impl HueBin {
+    fn add(&mut self, result: &ChromakeyResult, weight: f64) {
+        self.count += 1;
+        self.weight += weight;
+        self.hue.add_weighted(result.hue, weight as f32);
+        self.r_sum += result.color.r as f64 * weight;
+        self.g_sum += result.color.g as f64 * weight;
+        self.b_sum += result.color.b as f64 * weight;
+        self.confidence_sum += result.confidence as f64;
+        self.coverage_sum += result.coverage as f64 * weight;
+    }
+
+    /// Take back a frame passed to `add`
+    fn remove(&mut self, result: &ChromakeyResult, weight: f64) {
+        if self.count <= 1 {
+            // Start clean rather than keep rounding residue
+            *self = HueBin::default();
+            return;
+        }
+        self.count -= 1;
+        self.weight -= weight;
+        self.hue.remove_weighted(result.hue, weight as f32);
+        self.r_sum -= result.color.r as f64 * weight;
+        self.g_sum -= result.color.g as f64 * weight;
+        self.b_sum -= result.color.b as f64 * weight;
+        self.confidence_sum -= result.confidence as f64;
+        self.coverage_sum -= result.coverage as f64 * weight;
+    }
+
+    fn merge(&mut self, other: &HueBin) {
+        self.count += other.count;
+        self.weight += other.weight;
+        self.hue.merge(&other.hue);
+        self.r_sum += other.r_sum;
+        self.g_sum += other.g_sum;
+        self.b_sum += other.b_sum;
+        self.confidence_sum += other.confidence_sum;
+        self.coverage_sum += other.coverage_sum;
+    }
+}
+
+/// 360 one-degree bins: fixed memory no matter how many frames are added
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "SparseHueBins", try_from = "SparseHueBins")]
+struct HueBins {
+    bins: Vec<HueBin>,
+    total_weight: f64,
+}
+
+/// Saved form of `HueBins`: only the occupied bins, by hue
+#[derive(Serialize, Deserialize)]
+struct SparseHueBins {
+    bins: Vec<(usize, HueBin)>,
+    total_weight: f64,
+}
+
+impl From<HueBins> for SparseHueBins {
+    fn from(hue_bins: HueBins) -> Self {
+        let bins = hue_bins.bins.into_iter().enumerate().filter(|(_, bin)| bin.count > 0).collect();
+        Self { bins, total_weight: hue_bins.total_weight }
+    }
+}
+
+impl TryFrom<SparseHueBins> for HueBins {
+    type Error = String;
+
+    fn try_from(sparse: SparseHueBins) -> Result<Self, Self::Error> {
+        let mut hue_bins = HueBins::new();
+        for (index, bin) in sparse.bins {
+            *hue_bins.bins.get_mut(index).ok_or_else(|| format!("hue bin {} out of range", index))? = bin;
+        }
+        hue_bins.total_weight = sparse.total_weight;
+        Ok(hue_bins)
+    }
+}
+
+impl HueBins {
+    fn new() -> Self {
+        Self {
+            bins: vec![HueBin::default(); 360],
+            total_weight: 0.0,
+        }
+    }
+
+    /// `quality` scales the frame's confidence-based weight
+    fn add(&mut self, result: &ChromakeyResult, quality: f32) {
+        let weight = vote_weight(result, quality);
+        self.bins[hue_bin(result.hue)].add(result, weight);
+        self.total_weight += weight;
+    }
+
+    fn remove(&mut self, result: &ChromakeyResult, quality: f32) {
+        let weight = vote_weight(result, quality);
+        self.bins[hue_bin(result.hue)].remove(result, weight);
+        self.total_weight = (self.total_weight - weight).max(0.0);
+        if self.bins.iter().all(|bin| bin.count == 0) {
+            self.total_weight = 0.0;
+        }
+    }
+
+    fn merge(&mut self, other: &HueBins) {
+        for (bin, other) in self.bins.iter_mut().zip(&other.bins) {
+            bin.merge(other);
+        }
+        self.total_weight += other.total_weight;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.total_weight <= 0.0
+    }
+
+    /// Bin at the center of the heaviest ±GROUP_HUE_TOLERANCE window
+    /// (ties go to the lowest hue, so the choice is order independent)
+    fn densest(&self) -> usize {
+        let window = GROUP_HUE_TOLERANCE as isize;
+        let window_weight = |center: usize| -> f64 {
+            (-window..=window)
+                .map(|o| &self.bins[(center as isize + o).rem_euclid(360) as usize])
+                .map(|bin| bin.weight)
+                .sum()
+        };
+
+        let mut best = (0, f64::MIN);
+        for center in 0..360 {
+            let weight = window_weight(center);
+            if weight > best.1 {
+                best = (center, weight);
             }
         }
-        
-        groups
+        best.0
     }
-    
-    fn average_results(&self, results: &[&ChromakeyResult]) -> ChromakeyResult {
-        let mut avg_r = 0.0;
-        let mut avg_g = 0.0;
-        let mut avg_b = 0.0;
-        let mut avg_confidence = 0.0;
-        let mut avg_coverage = 0.0;
-        let mut avg_hue = 0.0;
-        
-        for result in results {
-            avg_r += result.color.r as f32;
-            avg_g += result.color.g as f32;
-            avg_b += result.color.b as f32;
-            avg_confidence += result.confidence;
-            avg_coverage += result.coverage;
-            avg_hue += result.hue;
+
+    /// Merge the bins around the densest hue whose hue sits within a few
+    /// MADs of the weighted median; far-off frames are left out
+    fn inliers(&self) -> HueBin {
+        let mut group = HueBin::default();
+        for index in self.inlier_bins() {
+            group.merge(&self.bins[index]);
         }
-        
-        let count = results.len() as f32;
-        
-        ChromakeyResult {
+        group
+    }
+
+    /// Indices of the bins `inliers` merges
+    fn inlier_bins(&self) -> Vec<usize> {
+        let center = self.densest();
+
+        // (offset of the bin's mean hue from the center, weight, bin index)
+        let mut nearby: Vec<(f32, f64, usize)> = Vec::new();
+        let spread = MAX_GROUP_SPREAD as isize;
+        for o in -spread..=spread {
+            let index = (center as isize + o).rem_euclid(360) as usize;
+            let bin = &self.bins[index];
+            if let Some(hue) = bin.hue.mean() {
+                nearby.push((signed_offset(center as f32, hue), bin.weight, index));
+            }
+        }
+
+        let median = weighted_median(nearby.iter().map(|&(offset, weight, _)| (offset, weight)));
+        let mad = weighted_median(nearby.iter().map(|&(offset, weight, _)| ((offset - median).abs(), weight)));
+        let band = (OUTLIER_MADS * MAD_TO_SIGMA * mad).max(MIN_INLIER_SPREAD);
+
+        nearby.into_iter()
+            .filter(|&(offset, _, _)| (offset - median).abs() <= band)
+            .map(|(_, _, index)| index)
+            .collect()
+    }
+}
+
+fn vote_weight(result: &ChromakeyResult, quality: f32) -> f64 {
+    (result.confidence * quality).max(MIN_FRAME_WEIGHT) as f64
+}
+
+fn hue_bin(hue: f32) -> usize {
+    (hue.rem_euclid(360.0) as usize).min(359)
+}
+
+/// Signed shortest turn from `from` to `to` in degrees (-180 - 180)
+fn signed_offset(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Median of (value, weight) pairs: the value where half the weight is reached
+fn weighted_median(values: impl Iterator<Item = (f32, f64)>) -> f32 {
+    let mut values: Vec<(f32, f64)> = values.filter(|&(_, w)| w > 0.0).collect();
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let half = values.iter().map(|&(_, w)| w).sum::<f64>() / 2.0;
+    let mut cumulative = 0.0;
+    for &(value, weight) in &values {
+        cumulative += weight;
+        if cumulative >= half {
+            return value;
+        }
+    }
+    0.0
+}
+
+impl KeyVotes {
+    fn new() -> Self {
+        Self {
+            confident: HueBins::new(),
+            weak: HueBins::new(),
+            low_quality: HueBins::new(),
+        }
+    }
+
+    fn bins_mut(&mut self, pool: VotePool) -> &mut HueBins {
+        match pool {
+            VotePool::Confident => &mut self.confident,
+            VotePool::Weak => &mut self.weak,
+            VotePool::LowQuality => &mut self.low_quality,
+        }
+    }
+
+    fn add(&mut self, result: &ChromakeyResult, quality: f32, pool: VotePool) {
+        self.bins_mut(pool).add(result, quality);
+    }
+
+    fn remove(&mut self, result: &ChromakeyResult, quality: f32, pool: VotePool) {
+        self.bins_mut(pool).remove(result, quality);
+    }
+
+    fn merge(&mut self, other: &KeyVotes) {
+        self.confident.merge(&other.confident);
+        self.weak.merge(&other.weak);
+        self.low_quality.merge(&other.low_quality);
+    }
+
+    /// The pool that votes: the first non-empty one in order of preference
+    fn voting_pool(&self) -> VotePool {
+        if !self.confident.is_empty() {
+            VotePool::Confident
+        } else if !self.weak.is_empty() {
+            VotePool::Weak
+        } else {
+            VotePool::LowQuality
+        }
+    }
+
+    /// The bins that vote, as `consensus` picks them
+    fn pool(&self) -> &HueBins {
+        match self.voting_pool() {
+            VotePool::Confident => &self.confident,
+            VotePool::Weak => &self.weak,
+            VotePool::LowQuality => &self.low_quality,
+        }
+    }
+
+    fn consensus(&self) -> Option<ChromakeyResult> {
+        // Strategy: Find the most consistent color across frames
+        // 1. Only frames that clear confidence_threshold and min_frame_quality
+        //    vote (unless none do; low quality is the last resort)
+        // 2. Find the densest hue, weighting each frame by its confidence
+        // 3. Drop frames far from the group's median hue (median/MAD)
+        // 4. Confidence-weighted average of what's left
+        let pool = self.pool();
+        if pool.is_empty() {
+            return None;
+        }
+
+        let group = pool.inliers();
+        if group.weight <= 0.0 {
+            return None;
+        }
+
+        // Agreement: share of the voting confidence that backs this color
+        let agreement = (group.weight / pool.total_weight) as f32;
+        let mean_confidence = (group.confidence_sum / group.count as f64) as f32;
+
+        Some(ChromakeyResult {
             color: RGB {
-                r: (avg_r / count).round() as u8,
-                g: (avg_g / count).round() as u8,
-                b: (avg_b / count).round() as u8,
+                r: (group.r_sum / group.weight).round() as u8,
+                g: (group.g_sum / group.weight).round() as u8,
+                b: (group.b_sum / group.weight).round() as u8,
             },
-            confidence: avg_confidence / count,
-            coverage: avg_coverage / count,
-            hue: avg_hue / count,
+            confidence: mean_confidence * agreement,
+            coverage: (group.coverage_sum / group.weight) as f32,
+            // Hue wraps at 360°, so it gets a circular mean
+            hue: group.hue.mean().unwrap_or(0.0),
             method_used: DetectionMethod::Hybrid,
+        })
+    }
+}
+
+impl VideoAnalyzer {
+    pub fn new(config: DetectionConfig) -> Self {
+        Self {
+            config,
+            video_config: VideoConfig::default(),
+            overall: KeyVotes::new(),
+            segments: Vec::new(),
+            first_signature: None,
+            last_signature: None,
+            frame_count: 0,
+            next_index: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn with_video_config(mut self, video_config: VideoConfig) -> Self {
+        self.video_config = video_config;
+        self
+    }
+
+    /// Detect the key in a frame and add it, starting a new segment when
+    /// the frame's colors differ too much from the previous frame's.
+    /// Frames without a key still extend the current segment.
+    pub fn add_frame<S: PixelSource>(&mut self, image: &S) -> Result<ChromakeyResult, DetectError> {
+        self.add_frame_with_time(image, None)
+    }
+
+    /// `add_frame` for a frame at `timestamp` (any unit, e.g. seconds),
+    /// reported back by `frames` and `timeline`
+    pub fn add_frame_at<S: PixelSource>(&mut self, image: &S, timestamp: f64) -> Result<ChromakeyResult, DetectError> {
+        self.add_frame_with_time(image, Some(timestamp))
+    }
+
+    fn add_frame_with_time<S: PixelSource>(
+        &mut self,
+        image: &S,
+        timestamp: Option<f64>,
+    ) -> Result<ChromakeyResult, DetectError> {
+        self.config.validate()?;
+
+        let signature = FrameSignature::from_image(image, self.config.grayscale_saturation);
+        let cut = self.last_signature.as_ref()
+            .is_some_and(|last| last.distance(&signature) > self.video_config.scene_cut_threshold);
+        if self.next_index == 0 {
+            self.first_signature = Some(signature.clone());
+        }
+        self.last_signature = Some(signature);
+
+        let mut quality = FrameQuality::from_image(image, self.config.grayscale_saturation);
+        let result = detect_in_image(image, &self.config);
+        if let Ok(key) = &result {
+            quality = quality.for_key(key, self.config.grayscale_saturation);
+        }
+        self.push_frame(result.clone(), Some(quality), timestamp, cut);
+        result
+    }
+
+    /// Add an already detected key (no scene-cut detection; it joins the current segment)
+    pub fn add_frame_result(&mut self, result: ChromakeyResult) {
+        self.push_frame(Ok(result), None, None, false);
+    }
+
+    pub fn add_frame_result_at(&mut self, result: ChromakeyResult, timestamp: f64) {
+        self.push_frame(Ok(result), None, Some(timestamp), false);
+    }
+
+    fn push_frame(
+        &mut self,
+        outcome: Result<ChromakeyResult, DetectError>,
+        quality: Option<FrameQuality>,
+        timestamp: Option<f64>,
+        cut: bool,
+    ) {
+        let index = self.next_index;
+        if cut || self.segments.is_empty() {
+            self.segments.push(Segment {
+                start_frame: index,
+                end_frame: index,
+                start_time: timestamp,
+                end_time: timestamp,
+                votes: KeyVotes::new(),
+            });
+        }
+
+        let segment_index = self.segments.len() - 1;
+        let segment = &mut self.segments[segment_index];
+        segment.end_frame = index;
+        segment.end_time = timestamp.or(segment.end_time);
+
+        let record = FrameRecord {
+            index,
+            timestamp,
+            segment: segment_index,
+            outcome,
+            quality,
+        };
+        if let Some((result, weight, pool)) = record.vote(self.video_config.min_frame_quality, self.config.confidence_threshold) {
+            segment.votes.add(result, weight, pool);
+            self.overall.add(result, weight, pool);
+        }
+
+        self.record_frame(record);
+        self.next_index += 1;
+        self.frame_count += 1;
+    }
+
+    /// Only the latest frames stay removable; older ones live on in the totals
+    fn record_frame(&mut self, record: FrameRecord) {
+        if self.video_config.frame_history == 0 {
+            return;
+        }
+        if self.history.len() >= self.video_config.frame_history {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+    }
+
+    /// Take a frame's vote back out of the consensus. Returns false if the
+    /// frame is unknown or has dropped out of the frame history.
+    /// Segment frame ranges are left as they were.
+    pub fn remove_frame(&mut self, index: u32) -> bool {
+        let Some(position) = self.history.iter().position(|record| record.index == index) else {
+            return false;
+        };
+        let record = self.history.remove(position).unwrap();
+        if let Some((result, weight, pool)) = record.vote(self.video_config.min_frame_quality, self.config.confidence_threshold) {
+            self.segments[record.segment].votes.remove(result, weight, pool);
+            self.overall.remove(result, weight, pool);
+        }
+        self.frame_count -= 1;
+        true
+    }
+
+    /// Append a session that analyzed the frames following this one's
+    /// (e.g. the next chunk, from another worker). Its frame indices are
+    /// shifted to follow ours, and its first segment continues our last
+    /// one unless the frames either side of the seam differ like a scene cut.
+    pub fn merge(&mut self, other: VideoAnalyzer) -> Result<(), SessionError> {
+        if self.config != other.config || self.video_config != other.video_config {
+            return Err(SessionError::ConfigMismatch);
+        }
+        if other.next_index == 0 {
+            return Ok(());
+        }
+        if self.next_index == 0 {
+            *self = other;
+            return Ok(());
+        }
+
+        let offset = self.next_index;
+        let cut = match (&self.last_signature, &other.first_signature) {
+            (Some(last), Some(first)) => last.distance(first) > self.video_config.scene_cut_threshold,
+            _ => false,
+        };
+        let segment_offset = if cut { self.segments.len() } else { self.segments.len() - 1 };
+
+        let mut segments = other.segments.into_iter();
+        if !cut {
+            let first = segments.next().unwrap();
+            let last = self.segments.last_mut().unwrap();
+            last.end_frame = first.end_frame + offset;
+            last.end_time = first.end_time.or(last.end_time);
+            last.votes.merge(&first.votes);
+        }
+        for mut segment in segments {
+            segment.start_frame += offset;
+            segment.end_frame += offset;
+            self.segments.push(segment);
+        }
+        self.overall.merge(&other.overall);
+
+        for mut record in other.history {
+            record.index += offset;
+            record.segment += segment_offset;
+            self.record_frame(record);
+        }
+
+        if other.last_signature.is_some() {
+            self.last_signature = other.last_signature;
+        }
+        self.frame_count += other.frame_count;
+        self.next_index += other.next_index;
+        Ok(())
+    }
+
+    /// Save the whole session (config, statistics, frame history) as
+    /// versioned JSON, to be picked up again with `from_json`
+    pub fn to_json(&self) -> String {
+        let saved = SavedSession { version: SESSION_VERSION, analyzer: self };
+        serde_json::to_string(&saved).expect("session state always serializes")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SessionError> {
+        let malformed = |e: serde_json::Error| SessionError::Malformed { message: e.to_string() };
+
+        // Check the version before trusting the rest of the layout
+        let header: SessionHeader = serde_json::from_str(json).map_err(malformed)?;
+        if header.version != SESSION_VERSION {
+            return Err(SessionError::UnsupportedVersion { version: header.version, supported: SESSION_VERSION });
+        }
+        let restored: RestoredSession = serde_json::from_str(json).map_err(malformed)?;
+        restored.analyzer.check_invariants().map_err(|message| SessionError::Malformed { message })?;
+        Ok(restored.analyzer)
+    }
+
+    /// What a session built through `add_frame` always satisfies, so a
+    /// tampered or truncated save can't panic later on
+    fn check_invariants(&self) -> Result<(), String> {
+        self.config.validate().map_err(|e| e.to_string())?;
+        self.video_config.validate().map_err(|e| e.to_string())?;
+        if self.segments.is_empty() != (self.next_index == 0) {
+            return Err(format!("{} segments for {} frames", self.segments.len(), self.next_index));
+        }
+        if self.history.len() > self.frame_count as usize {
+            return Err(format!("{} frames in history but {} in the session", self.history.len(), self.frame_count));
         }
+        for record in &self.history {
+            if record.segment >= self.segments.len() {
+                return Err(format!("frame {} is in segment {} of {}", record.index, record.segment, self.segments.len()));
+            }
+            if record.index >= self.next_index {
+                return Err(format!("frame {} is past the session's {} frames", record.index, self.next_index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop every frame and start a fresh session with the same configuration
+    pub fn reset(&mut self) {
+        self.overall = KeyVotes::new();
+        self.segments.clear();
+        self.first_signature = None;
+        self.last_signature = None;
+        self.frame_count = 0;
+        self.next_index = 0;
+        self.history.clear();
+    }
+
+    /// Number of frames in the session, with or without a key
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+    
+    /// One key for the whole video
+    pub fn compute_consensus(&self) -> Option<ChromakeyResult> {
+        self.overall.consensus()
+    }
+
+    /// Segments between detected scene cuts, each with its own consensus
+    pub fn timeline(&self) -> Vec<VideoSegment> {
+        self.segments.iter().map(|segment| {
+            let key = segment.votes.consensus();
+            VideoSegment {
+                start_frame: segment.start_frame,
+                end_frame: segment.end_frame,
+                start_time: segment.start_time,
+                end_time: segment.end_time,
+                confidence: key.as_ref().map_or(0.0, |k| k.confidence),
+                key,
+            }
+        }).collect()
+    }
+
+    /// Per-frame results for the frames still in the history, oldest first,
+    /// with how each one fared against its segment's consensus
+    pub fn frames(&self) -> Vec<FrameReport> {
+        // (segment whose inliers are cached, pool voting, inlier bins)
+        let mut cached: Option<(usize, VotePool, Vec<usize>)> = None;
+
+        self.history.iter().map(|record| {
+            let vote = record.vote(self.video_config.min_frame_quality, self.config.confidence_threshold);
+            let status = match vote {
+                None => FrameStatus::NoKey,
+                Some((result, _, pool)) => {
+                    if cached.as_ref().is_none_or(|(segment, _, _)| *segment != record.segment) {
+                        let votes = &self.segments[record.segment].votes;
+                        cached = Some((record.segment, votes.voting_pool(), votes.pool().inlier_bins()));
+                    }
+                    let (_, voting_pool, inliers) = cached.as_ref().unwrap();
+
+                    if pool != *voting_pool {
+                        if pool == VotePool::LowQuality { FrameStatus::LowQuality } else { FrameStatus::LowConfidence }
+                    } else if inliers.contains(&hue_bin(result.hue)) {
+                        FrameStatus::Accepted
+                    } else {
+                        FrameStatus::Outlier
+                    }
+                }
+            };
+
+            FrameReport {
+                index: record.index,
+                timestamp: record.timestamp,
+                segment: record.segment,
+                status,
+                result: record.outcome.as_ref().ok().cloned(),
+                error: record.outcome.as_ref().err().cloned(),
+                quality: record.quality,
+            }
+        }).collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color::{HSV, angular_distance};
+    use crate::pixel_format::ImageView;
+
+    fn frame(hue: f32, confidence: f32) -> ChromakeyResult {
+        ChromakeyResult {
+            color: HSV { h: hue, s: 1.0, v: 1.0 }.to_rgb(),
+            confidence,
+            coverage: 0.5,
+            hue,
+            method_used: DetectionMethod::Edge,
+        }
+    }
 
     #[test]
     fn test_video_consensus_perfect_agreement() {
@@ -171,4 +1000,517 @@ mod tests {
         // Confidence = 0.9 * 0.8 = 0.72
         assert!((consensus.confidence - 0.72).abs() < 0.01);
     }
+
+    #[test]
+    fn test_video_consensus_red_wraps() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+
+        // Red key drifting across 0°
+        for hue in [356.0, 2.0, 359.0, 4.0] {
+            analyzer.add_frame_result(ChromakeyResult {
+                color: RGB { r: 255, g: 0, b: 0 },
+                confidence: 0.9,
+                coverage: 0.5,
+                hue,
+                method_used: DetectionMethod::Edge,
+            });
+        }
+
+        let consensus = analyzer.compute_consensus().unwrap();
+
+        assert!(angular_distance(consensus.hue, 0.25) < 0.1, "hue = {}", consensus.hue);
+        assert!((consensus.confidence - 0.9).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_video_memory_bounded() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+
+        // A long session of jittery green with occasional blue
+        let (mut green_hue_sum, mut green_frames) = (0.0, 0.0);
+        for i in 0..10_000 {
+            let (hue, color) = if i % 10 == 0 {
+                (240.0, RGB { r: 0, g: 0, b: 255 })
+            } else {
+                let hue = 118.0 + (i % 5) as f32;
+                green_hue_sum += hue;
+                green_frames += 1.0;
+                (hue, RGB { r: 0, g: 255, b: 0 })
+            };
+            analyzer.add_frame_result(ChromakeyResult {
+                color,
+                confidence: 0.8,
+                coverage: 0.5,
+                hue,
+                method_used: DetectionMethod::Edge,
+            });
+        }
+
+        assert_eq!(analyzer.frame_count(), 10_000);
+        // Memory is the fixed bin table, however many frames arrive
+        let votes = &analyzer.overall;
+        assert_eq!(votes.confident.bins.len() + votes.weak.bins.len() + votes.low_quality.bins.len(), 1080);
+
+        let consensus = analyzer.compute_consensus().unwrap();
+        assert!((consensus.hue - green_hue_sum / green_frames).abs() < 0.01, "hue = {}", consensus.hue);
+        assert!((consensus.confidence - 0.72).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_consensus_is_order_independent() {
+        // Hues chained 8° apart: first-member anchoring splits these differently
+        // depending on which frame comes first
+        let hues = [100.0, 108.0, 116.0, 124.0, 116.0, 108.0];
+        let mut forward = VideoAnalyzer::new(DetectionConfig::default());
+        let mut backward = VideoAnalyzer::new(DetectionConfig::default());
+        for &hue in &hues {
+            forward.add_frame_result(frame(hue, 0.9));
+        }
+        for &hue in hues.iter().rev() {
+            backward.add_frame_result(frame(hue, 0.9));
+        }
+
+        let a = forward.compute_consensus().unwrap();
+        let b = backward.compute_consensus().unwrap();
+        assert!((a.hue - b.hue).abs() < 1e-3, "{} vs {}", a.hue, b.hue);
+        assert!((a.confidence - b.confidence).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_consensus_weights_confidence() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig { confidence_threshold: 0.0, ..DetectionConfig::default() });
+
+        // One strong frame and one weak frame two degrees apart
+        analyzer.add_frame_result(frame(118.0, 0.9));
+        analyzer.add_frame_result(frame(120.0, 0.1));
+
+        let consensus = analyzer.compute_consensus().unwrap();
+        // Weighted 9:1 towards 118
+        assert!((consensus.hue - 118.2).abs() < 0.05, "hue = {}", consensus.hue);
+        // Agreement is total, so confidence is the plain mean
+        assert!((consensus.confidence - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_consensus_rejects_outliers() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+        for hue in [119.0, 120.0, 120.0, 121.0, 120.0, 119.0, 121.0, 120.0] {
+            analyzer.add_frame_result(frame(hue, 0.9));
+        }
+        // Inside the grouping window, but far outside the group's spread
+        analyzer.add_frame_result(frame(136.0, 0.9));
+
+        let consensus = analyzer.compute_consensus().unwrap();
+        assert!((consensus.hue - 120.0).abs() < 0.05, "hue = {}", consensus.hue);
+        assert!((consensus.confidence - 0.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_consensus_uses_confidence_threshold() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+
+        // Many low-confidence blue frames, fewer confident green ones
+        for _ in 0..6 {
+            analyzer.add_frame_result(frame(240.0, 0.4));
+        }
+        for _ in 0..2 {
+            analyzer.add_frame_result(frame(120.0, 0.8));
+        }
+
+        let consensus = analyzer.compute_consensus().unwrap();
+        assert!((consensus.hue - 120.0).abs() < 0.1, "hue = {}", consensus.hue);
+
+        // With nothing above the threshold, the weak frames still decide
+        let mut weak_only = VideoAnalyzer::new(DetectionConfig::default());
+        weak_only.add_frame_result(frame(240.0, 0.4));
+        let consensus = weak_only.compute_consensus().unwrap();
+        assert!((consensus.hue - 240.0).abs() < 0.1);
+        assert!((consensus.confidence - 0.4).abs() < 1e-6);
+    }
+
+    fn screen(width: u32, height: u32, backdrop: [u8; 3], subject_x: u32) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let in_subject = (subject_x..subject_x + width / 4).contains(&x) && y > height / 3;
+                let rgb = if in_subject { [200, 160, 130] } else { backdrop };
+                pixels.extend_from_slice(&rgb);
+                pixels.push(255);
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_scene_cut_segments() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+        let (width, height) = (80, 60);
+
+        // Green shot with a moving subject, then a blue shot
+        for i in 0..4 {
+            let pixels = screen(width, height, [20, 200, 40], 10 + i * 5);
+            analyzer.add_frame(&ImageView::rgba(&pixels, width, height).unwrap()).unwrap();
+        }
+        for i in 0..3 {
+            let pixels = screen(width, height, [30, 40, 210], 30 - i * 5);
+            analyzer.add_frame(&ImageView::rgba(&pixels, width, height).unwrap()).unwrap();
+        }
+
+        let timeline = analyzer.timeline();
+        assert_eq!(timeline.len(), 2, "{:?}", timeline);
+        assert_eq!((timeline[0].start_frame, timeline[0].end_frame), (0, 3));
+        assert_eq!((timeline[1].start_frame, timeline[1].end_frame), (4, 6));
+
+        let green = timeline[0].key.as_ref().unwrap();
+        let blue = timeline[1].key.as_ref().unwrap();
+        assert!((green.hue - 127.0).abs() < 5.0, "green hue = {}", green.hue);
+        assert!((blue.hue - 236.0).abs() < 5.0, "blue hue = {}", blue.hue);
+        assert!(timeline.iter().all(|segment| segment.confidence > 0.5));
+    }
+
+    #[test]
+    fn test_frames_without_key_extend_segment() {
+        let config = DetectionConfig { use_alpha: true, ..DetectionConfig::default() };
+        let mut analyzer = VideoAnalyzer::new(config);
+        analyzer.add_frame_result(frame(120.0, 0.9));
+
+        // Fully transparent frame: no key, same segment (no signature to compare against yet)
+        let transparent = vec![0u8; 40 * 40 * 4];
+        assert!(analyzer.add_frame(&ImageView::rgba(&transparent, 40, 40).unwrap()).is_err());
+
+        let timeline = analyzer.timeline();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].end_frame, 1);
+        assert_eq!(analyzer.frame_count(), 2);
+    }
+
+    #[test]
+    fn test_frame_reports() {
+        let config = DetectionConfig { use_alpha: true, ..DetectionConfig::default() };
+        let mut analyzer = VideoAnalyzer::new(config);
+        for i in 0..4 {
+            analyzer.add_frame_result_at(frame(120.0, 0.9), i as f64 * 0.5);
+        }
+        analyzer.add_frame_result_at(frame(240.0, 0.9), 2.0);
+        analyzer.add_frame_result_at(frame(120.0, 0.4), 2.5);
+        let transparent = vec![0u8; 40 * 40 * 4];
+        assert!(analyzer.add_frame_at(&ImageView::rgba(&transparent, 40, 40).unwrap(), 3.0).is_err());
+
+        let frames = analyzer.frames();
+        let statuses: Vec<FrameStatus> = frames.iter().map(|f| f.status).collect();
+        assert_eq!(statuses, [
+            FrameStatus::Accepted,
+            FrameStatus::Accepted,
+            FrameStatus::Accepted,
+            FrameStatus::Accepted,
+            FrameStatus::Outlier,
+            FrameStatus::LowConfidence,
+            FrameStatus::NoKey,
+        ]);
+        assert_eq!(frames[4].timestamp, Some(2.0));
+        assert_eq!(frames[6].error, Some(DetectError::Transparent));
+        assert!(frames[6].result.is_none());
+
+        let timeline = analyzer.timeline();
+        assert_eq!((timeline[0].start_time, timeline[0].end_time), (Some(0.0), Some(3.0)));
+    }
+
+    #[test]
+    fn test_remove_frame_and_reset() {
+        let video_config = VideoConfig { frame_history: 3, ..VideoConfig::default() };
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default()).with_video_config(video_config);
+        for _ in 0..3 {
+            analyzer.add_frame_result(frame(120.0, 0.9));
+        }
+        analyzer.add_frame_result(frame(240.0, 0.9));
+        assert!((analyzer.compute_consensus().unwrap().confidence - 0.675).abs() < 1e-6);
+
+        // Frame 0 has dropped out of the history; the blue frame can still go
+        assert!(!analyzer.remove_frame(0));
+        assert!(analyzer.remove_frame(3));
+        assert!(!analyzer.remove_frame(3));
+        assert_eq!(analyzer.frame_count(), 3);
+        let consensus = analyzer.compute_consensus().unwrap();
+        assert!((consensus.hue - 120.0).abs() < 0.01);
+        assert!((consensus.confidence - 0.9).abs() < 1e-6);
+        assert_eq!(analyzer.frames().len(), 2);
+
+        // Removing every frame of a bin leaves no trace of it
+        assert!(analyzer.remove_frame(1));
+        assert!(analyzer.remove_frame(2));
+        assert!((analyzer.compute_consensus().unwrap().confidence - 0.9).abs() < 1e-6);
+
+        analyzer.reset();
+        assert_eq!(analyzer.frame_count(), 0);
+        assert!(analyzer.compute_consensus().is_none());
+        assert!(analyzer.timeline().is_empty());
+        assert!(analyzer.frames().is_empty());
+    }
+
+    /// Box blur of an RGBA frame along both axes
+    fn blur(pixels: &[u8], width: u32, height: u32, radius: i32) -> Vec<u8> {
+        let (w, h) = (width as i32, height as i32);
+        let mut out = pixels.to_vec();
+        for (dx, dy) in [(1, 0), (0, 1)] {
+            let src = out.clone();
+            for y in 0..h {
+                for x in 0..w {
+                    for c in 0..3 {
+                        let mut sum = 0u32;
+                        for o in -radius..=radius {
+                            let sx = (x + o * dx).clamp(0, w - 1);
+                            let sy = (y + o * dy).clamp(0, h - 1);
+                            sum += src[((sy * w + sx) * 4 + c) as usize] as u32;
+                        }
+                        out[((y * w + x) * 4 + c) as usize] = (sum / (2 * radius as u32 + 1)) as u8;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_frame_quality() {
+        let (width, height) = (80, 60);
+        let quality = |pixels: &[u8]| FrameQuality::from_image(&ImageView::rgba(pixels, width, height).unwrap(), 0.15);
+
+        let sharp = screen(width, height, [20, 200, 40], 20);
+        let good = quality(&sharp);
+        assert!(good.sharpness > 0.9, "{:?}", good);
+        assert_eq!((good.exposure, good.grayscale), (1.0, 0.0));
+
+        let blurred = quality(&blur(&sharp, width, height, 4));
+        assert!(blurred.sharpness < 0.2, "{:?}", blurred);
+        assert!(blurred.score < 0.2);
+
+        let black = quality(&screen(width, height, [0, 0, 0], width));
+        assert_eq!((black.exposure, black.score), (0.0, 0.0));
+        let gray = quality(&screen(width, height, [128, 128, 128], width));
+        assert_eq!((gray.grayscale, gray.score), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_low_quality_frames_dont_vote() {
+        let (width, height) = (80, 60);
+        let sharp = screen(width, height, [20, 200, 40], 20);
+        // Blurred and tinted towards cyan: a different key if it voted
+        let mut smeared = blur(&sharp, width, height, 4);
+        smeared.chunks_exact_mut(4).for_each(|p| p[2] = p[2].saturating_add(90));
+
+        let mut with_blur = VideoAnalyzer::new(DetectionConfig::default())
+            .with_video_config(VideoConfig { scene_cut_threshold: 1.0, ..VideoConfig::default() });
+        let mut without = VideoAnalyzer::new(DetectionConfig::default());
+        for analyzer in [&mut with_blur, &mut without] {
+            for _ in 0..2 {
+                analyzer.add_frame(&ImageView::rgba(&sharp, width, height).unwrap()).unwrap();
+            }
+        }
+        assert!(with_blur.add_frame_at(&ImageView::rgba(&smeared, width, height).unwrap(), 1.0).is_ok());
+
+        let report = &with_blur.frames()[2];
+        assert_eq!(report.status, FrameStatus::LowQuality);
+        assert!(report.quality.unwrap().sharpness < 0.2);
+        assert_eq!(with_blur.frames()[0].status, FrameStatus::Accepted);
+
+        let (a, b) = (with_blur.compute_consensus().unwrap(), without.compute_consensus().unwrap());
+        assert_eq!((a.hue, a.confidence), (b.hue, b.confidence));
+
+        // Removing a fallback frame leaves the consensus alone
+        assert!(with_blur.remove_frame(2));
+        assert_eq!(with_blur.compute_consensus().unwrap().hue, b.hue);
+    }
+
+    #[test]
+    fn test_black_backdrop_votes() {
+        // A sharp black-backdrop frame is a grayscale key, not a bad exposure
+        let (width, height) = (80, 60);
+        let pixels = screen(width, height, [0, 0, 0], 20);
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default())
+            .with_video_config(VideoConfig { scene_cut_threshold: 1.0, ..VideoConfig::default() });
+        let key = analyzer.add_frame(&ImageView::rgba(&pixels, width, height).unwrap()).unwrap();
+        assert!(key.color.to_hsv().v < 0.1, "{:?}", key);
+
+        let report = &analyzer.frames()[0];
+        let quality = report.quality.unwrap();
+        assert_eq!(quality.score, quality.sharpness);
+        assert!(quality.exposure < 0.5 && quality.score > 0.9, "{:?}", quality);
+        assert_eq!(report.status, FrameStatus::Accepted);
+
+        // It still outvotes a blurred green frame
+        let soft = blur(&screen(width, height, [20, 200, 40], 20), width, height, 3);
+        analyzer.add_frame(&ImageView::rgba(&soft, width, height).unwrap()).unwrap();
+        assert_eq!(analyzer.frames()[1].status, FrameStatus::LowQuality);
+        let consensus = analyzer.compute_consensus().unwrap();
+        assert_eq!((consensus.color.r, consensus.color.g, consensus.color.b), (key.color.r, key.color.g, key.color.b));
+    }
+
+    #[test]
+    fn test_low_quality_fallback() {
+        // Every frame is soft (7-px box blur): they still decide the key
+        let (width, height) = (80, 60);
+        let soft = blur(&screen(width, height, [20, 200, 40], 20), width, height, 3);
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+        for _ in 0..3 {
+            analyzer.add_frame(&ImageView::rgba(&soft, width, height).unwrap()).unwrap();
+        }
+
+        let report = &analyzer.frames()[0];
+        assert!(report.quality.unwrap().score < VideoConfig::default().min_frame_quality, "{:?}", report.quality);
+        assert_eq!(report.status, FrameStatus::Accepted);
+
+        let consensus = analyzer.compute_consensus().expect("low-quality frames are the fallback");
+        assert!(angular_distance(consensus.hue, 130.0) < 10.0, "hue = {}", consensus.hue);
+        assert_eq!(analyzer.timeline()[0].key.as_ref().map(|k| k.hue), Some(consensus.hue));
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+        let green = screen(80, 60, [20, 200, 40], 20);
+        analyzer.add_frame_at(&ImageView::rgba(&green, 80, 60).unwrap(), 0.0).unwrap();
+        for (i, hue) in [119.0, 121.0, 240.0].into_iter().enumerate() {
+            analyzer.add_frame_result_at(frame(hue, 0.9), 1.0 + i as f64);
+        }
+
+        let json = analyzer.to_json();
+        let mut restored = VideoAnalyzer::from_json(&json).unwrap();
+        assert_eq!(restored.to_json(), json);
+        assert_eq!(restored.frame_count(), 4);
+        assert_eq!(restored.frames()[3].status, FrameStatus::Outlier);
+
+        // Both carry on identically, scene-cut detection included
+        let blue = screen(80, 60, [30, 40, 210], 20);
+        for session in [&mut analyzer, &mut restored] {
+            session.add_frame_at(&ImageView::rgba(&blue, 80, 60).unwrap(), 4.0).unwrap();
+        }
+        assert_eq!(restored.to_json(), analyzer.to_json());
+        assert_eq!(restored.timeline().len(), 2);
+    }
+
+    #[test]
+    fn test_session_version_checked() {
+        let json = VideoAnalyzer::new(DetectionConfig::default()).to_json();
+        let future = json.replacen("\"version\":1", "\"version\":2", 1);
+        assert_eq!(
+            VideoAnalyzer::from_json(&future).err(),
+            Some(SessionError::UnsupportedVersion { version: 2, supported: SESSION_VERSION })
+        );
+        assert!(matches!(VideoAnalyzer::from_json("{\"version\":1}"), Err(SessionError::Malformed { .. })));
+        assert!(matches!(VideoAnalyzer::from_json("not json"), Err(SessionError::Malformed { .. })));
+    }
+
+    #[test]
+    fn test_tampered_session_rejected() {
+        let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+        analyzer.add_frame_result(frame(120.0, 0.9));
+        let json = analyzer.to_json();
+        assert!(VideoAnalyzer::from_json(&json).is_ok());
+
+        let tampered = [
+            json.replacen("\"segment\":0", "\"segment\":5", 1),
+            json.replacen("\"index\":0", "\"index\":7", 1),
+            json.replacen("\"next_index\":1", "\"next_index\":0", 1),
+            json.replacen("\"frame_count\":1", "\"frame_count\":0", 1),
+            json.replacen("\"min_saturation\":0.3", "\"min_saturation\":3.0", 1),
+        ];
+        for json in &tampered {
+            assert!(
+                matches!(VideoAnalyzer::from_json(json), Err(SessionError::Malformed { .. })),
+                "accepted {}", json
+            );
+        }
+
+        // The tampered frame would otherwise index past the segments
+        let restored = VideoAnalyzer::from_json(&tampered[0]);
+        assert!(restored.err().unwrap().to_string().contains("segment 5"));
+    }
+
+    #[test]
+    fn test_merge_matches_single_session() {
+        let hues = [118.0, 120.0, 122.0, 240.0, 121.0, 119.0, 136.0, 120.0];
+        let mut whole = VideoAnalyzer::new(DetectionConfig::default());
+        let mut first = VideoAnalyzer::new(DetectionConfig::default());
+        let mut second = VideoAnalyzer::new(DetectionConfig::default());
+        for (i, &hue) in hues.iter().enumerate() {
+            whole.add_frame_result_at(frame(hue, 0.9), i as f64);
+            let half = if i < 4 { &mut first } else { &mut second };
+            half.add_frame_result_at(frame(hue, 0.9), i as f64);
+        }
+
+        // Round-trip the second half as a worker would hand it over
+        first.merge(VideoAnalyzer::from_json(&second.to_json()).unwrap()).unwrap();
+
+        let (a, b) = (first.compute_consensus().unwrap(), whole.compute_consensus().unwrap());
+        assert!((a.hue - b.hue).abs() < 1e-3, "{} vs {}", a.hue, b.hue);
+        assert!((a.confidence - b.confidence).abs() < 1e-6);
+        assert_eq!(first.frame_count(), 8);
+
+        let indices: Vec<u32> = first.frames().iter().map(|f| f.index).collect();
+        assert_eq!(indices, (0..8).collect::<Vec<_>>());
+        let statuses = |analyzer: &VideoAnalyzer| analyzer.frames().iter().map(|f| f.status).collect::<Vec<_>>();
+        assert_eq!(statuses(&first), statuses(&whole));
+
+        let timeline = first.timeline();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!((timeline[0].end_frame, timeline[0].end_time), (7, Some(7.0)));
+
+        let other_config = DetectionConfig { confidence_threshold: 0.5, ..DetectionConfig::default() };
+        assert_eq!(first.merge(VideoAnalyzer::new(other_config)).err(), Some(SessionError::ConfigMismatch));
+    }
+
+    #[test]
+    fn test_merge_splits_at_scene_cut() {
+        let green = screen(80, 60, [20, 200, 40], 20);
+        let blue = screen(80, 60, [30, 40, 210], 20);
+        let session = |pixels: &[u8]| {
+            let mut analyzer = VideoAnalyzer::new(DetectionConfig::default());
+            for _ in 0..2 {
+                analyzer.add_frame(&ImageView::rgba(pixels, 80, 60).unwrap()).unwrap();
+            }
+            analyzer
+        };
+
+        let mut same_shot = session(&green);
+        same_shot.merge(session(&green)).unwrap();
+        assert_eq!(same_shot.timeline().len(), 1);
+
+        let mut cut = session(&green);
+        cut.merge(session(&blue)).unwrap();
+        let timeline = cut.timeline();
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[1].start_frame, timeline[1].end_frame), (2, 3));
+        assert_eq!(cut.frames()[3].segment, 1);
+    }
+
+    #[test]
+    fn test_signature_distance() {
+        let green = screen(64, 64, [20, 200, 40], 10);
+        let shifted = screen(64, 64, [20, 200, 40], 30);
+        let blue = screen(64, 64, [30, 40, 210], 10);
+        let signature = |pixels: &[u8]| FrameSignature::from_image(&ImageView::rgba(pixels, 64, 64).unwrap(), 0.15);
+
+        assert_eq!(signature(&green).distance(&signature(&green)), 0.0);
+        assert!(signature(&green).distance(&signature(&shifted)) < 0.1);
+        assert!(signature(&green).distance(&signature(&blue)) > 0.5);
+    }
+
+    #[test]
+    fn test_video_config_validation() {
+        assert!(VideoConfig::default().validate().is_ok());
+
+        for threshold in [f32::NAN, -0.1, 1.5] {
+            let config = VideoConfig { scene_cut_threshold: threshold, ..VideoConfig::default() };
+            assert!(matches!(
+                config.validate(),
+                Err(DetectError::ConfigOutOfRange { field, .. }) if field == "scene_cut_threshold"
+            ));
+        }
+
+        let config = VideoConfig { min_frame_quality: 1.5, ..VideoConfig::default() };
+        assert!(matches!(
+            config.validate(),
+            Err(DetectError::ConfigOutOfRange { field, .. }) if field == "min_frame_quality"
+        ));
+    }
 }
@@ -0,0 +1,352 @@
+use serde::{Deserialize, Serialize};
+use crate::color::{RGB, YCbCr, YCbCrMatrix};
+use crate::detection::{ChromakeyResult, DetectError};
+use crate::pixel_format::PixelSource;
+
+/// Planar 4:2:0 layouts (chroma at half resolution in both directions)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YuvFormat {
+    #[default]
+    I420,           // Y plane, then separate U and V planes
+    Nv12,           // Y plane, then one interleaved UV plane
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YuvLayout {
+    pub format: YuvFormat,
+    #[serde(alias = "yStride")]
+    pub y_stride: usize,        // Bytes per luma row; 0 = width
+    #[serde(alias = "uvStride")]
+    pub uv_stride: usize,       // Bytes per chroma row; 0 = tightly packed
+    pub matrix: YCbCrMatrix,    // Default: BT.709
+    #[serde(alias = "fullRange")]
+    pub full_range: bool,       // Default: false (16 - 235 video range, as decoders emit)
+}
+
+/// Detection result for YUV input: the usual RGB key plus its Cb/Cr point,
+/// in the same range as the input planes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct YuvChromakeyResult {
+    #[serde(flatten)]
+    pub result: ChromakeyResult,
+    pub cb: f32,
+    pub cr: f32,
+}
+
+/// A validated, read-only view over I420 or NV12 planes
+#[derive(Clone, Copy)]
+pub struct YuvImage<'a> {
+    y: &'a [u8],
+    u: &'a [u8],
+    v: &'a [u8],
+    width: u32,
+    height: u32,
+    y_stride: usize,
+    uv_stride: usize,
+    layout: YuvLayout,
+}
+
+impl<'a> YuvImage<'a> {
+    /// `u` and `v` are the chroma planes. For NV12 pass the interleaved UV
+    /// plane as `u`; `v` is ignored.
+    pub fn new(
+        y: &'a [u8],
+        u: &'a [u8],
+        v: &'a [u8],
+        width: u32,
+        height: u32,
+        layout: YuvLayout,
+    ) -> Result<Self, DetectError> {
+        if width == 0 || height == 0 {
+            return Err(DetectError::InvalidDimensions { width, height });
+        }
+
+        let invalid = || DetectError::InvalidDimensions { width, height };
+        let y_stride = resolve_stride(layout.y_stride, width as usize)?;
+        let uv_row = chroma_row_bytes(width, layout.format);
+        let uv_stride = resolve_stride(layout.uv_stride, uv_row)?;
+
+        let y_len = plane_len(y_stride, width as usize, height as usize).ok_or_else(invalid)?;
+        check_plane(y, y_len)?;
+        let uv_len = plane_len(uv_stride, uv_row, height.div_ceil(2) as usize).ok_or_else(invalid)?;
+        check_plane(u, uv_len)?;
+        if layout.format == YuvFormat::I420 {
+            check_plane(v, uv_len)?;
+        }
+
+        Ok(Self { y, u, v, width, height, y_stride, uv_stride, layout })
+    }
+
+    /// Split a single buffer holding the planes back to back
+    pub fn contiguous(data: &'a [u8], width: u32, height: u32, layout: YuvLayout) -> Result<Self, DetectError> {
+        if width == 0 || height == 0 {
+            return Err(DetectError::InvalidDimensions { width, height });
+        }
+
+        let invalid = || DetectError::InvalidDimensions { width, height };
+        let y_stride = resolve_stride(layout.y_stride, width as usize)?;
+        let uv_row = chroma_row_bytes(width, layout.format);
+        let uv_stride = resolve_stride(layout.uv_stride, uv_row)?;
+        let chroma_height = height.div_ceil(2) as usize;
+
+        // Each plane keeps its row padding, except the buffer's very last row
+        // (the same rule `new` applies to every plane)
+        let y_len = y_stride.checked_mul(height as usize).ok_or_else(invalid)?;
+        let uv_len = uv_stride.checked_mul(chroma_height).ok_or_else(invalid)?;
+        let last_plane = plane_len(uv_stride, uv_row, chroma_height).ok_or_else(invalid)?;
+        let expected = match layout.format {
+            YuvFormat::I420 => y_len.checked_add(uv_len),
+            YuvFormat::Nv12 => Some(y_len),
+        }
+        .and_then(|len| len.checked_add(last_plane))
+        .ok_or_else(invalid)?;
+        if data.len() < expected {
+            return Err(DetectError::BufferTooShort { expected, actual: data.len() });
+        }
+
+        let (y, chroma) = data.split_at(y_len);
+        let (u, v) = match layout.format {
+            YuvFormat::I420 => chroma.split_at(uv_len),
+            YuvFormat::Nv12 => (chroma, &[][..]),
+        };
+        Self::new(y, u, v, width, height, layout)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layout(&self) -> YuvLayout {
+        self.layout
+    }
+
+    /// The image sampled once per chroma sample, for the detection stages
+    pub fn chroma(&self) -> ChromaSamples<'_, 'a> {
+        ChromaSamples { image: self }
+    }
+
+    /// Cb/Cr of an RGB color, in this image's range
+    pub fn cb_cr(&self, color: RGB) -> (f32, f32) {
+        let ycc = color.to_ycbcr(self.layout.matrix);
+        if self.layout.full_range {
+            (ycc.cb, ycc.cr)
+        } else {
+            (to_video_chroma(ycc.cb), to_video_chroma(ycc.cr))
+        }
+    }
+
+    /// Full-range YCbCr of one chroma sample, with luma averaged over the
+    /// pixels it covers
+    fn sample(&self, cx: u32, cy: u32) -> YCbCr {
+        let (x0, y0) = (cx * 2, cy * 2);
+        let mut luma = 0u32;
+        let mut count = 0u32;
+        for y in y0..(y0 + 2).min(self.height) {
+            let row = y as usize * self.y_stride;
+            for x in x0..(x0 + 2).min(self.width) {
+                luma += self.y[row + x as usize] as u32;
+                count += 1;
+            }
+        }
+
+        let row = cy as usize * self.uv_stride;
+        let (cb, cr) = match self.layout.format {
+            YuvFormat::I420 => (self.u[row + cx as usize], self.v[row + cx as usize]),
+            YuvFormat::Nv12 => {
+                let idx = row + cx as usize * 2;
+                (self.u[idx], self.u[idx + 1])
+            }
+        };
+
+        let y = luma as f32 / count as f32;
+        let (cb, cr) = (cb as f32, cr as f32);
+        if self.layout.full_range {
+            YCbCr { y, cb, cr }
+        } else {
+            YCbCr {
+                y: (y - 16.0) * 255.0 / 219.0,
+                cb: (cb - 128.0) * 255.0 / 224.0 + 128.0,
+                cr: (cr - 128.0) * 255.0 / 224.0 + 128.0,
+            }
+        }
+    }
+}
+
+/// `YuvImage` at its native chroma resolution
+pub struct ChromaSamples<'b, 'a> {
+    image: &'b YuvImage<'a>,
+}
+
+impl PixelSource for ChromaSamples<'_, '_> {
+    fn width(&self) -> u32 {
+        self.image.width.div_ceil(2)
+    }
+
+    fn height(&self) -> u32 {
+        self.image.height.div_ceil(2)
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> RGB {
+        self.image.sample(x, y).to_rgb(self.image.layout.matrix)
+    }
+}
+
+fn chroma_row_bytes(width: u32, format: YuvFormat) -> usize {
+    let samples = width.div_ceil(2) as usize;
+    match format {
+        YuvFormat::I420 => samples,
+        YuvFormat::Nv12 => samples * 2,
+    }
+}
+
+fn resolve_stride(stride: usize, row_bytes: usize) -> Result<usize, DetectError> {
+    if stride == 0 {
+        Ok(row_bytes)
+    } else if stride < row_bytes {
+        Err(DetectError::InvalidRowStride { row_stride: stride, min: row_bytes })
+    } else {
+        Ok(stride)
+    }
+}
+
+/// Bytes a plane of `rows` rows needs; the last row doesn't need its
+/// padding. None if that overflows.
+fn plane_len(stride: usize, row_bytes: usize, rows: usize) -> Option<usize> {
+    stride.checked_mul(rows - 1)?.checked_add(row_bytes)
+}
+
+fn check_plane(plane: &[u8], expected: usize) -> Result<(), DetectError> {
+    if plane.len() < expected {
+        return Err(DetectError::BufferTooShort { expected, actual: plane.len() });
+    }
+    Ok(())
+}
+
+fn to_video_chroma(c: f32) -> f32 {
+    (c - 128.0) * 224.0 / 255.0 + 128.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video_range(rgb: RGB) -> (u8, u8, u8) {
+        let ycc = rgb.to_ycbcr(YCbCrMatrix::Bt709);
+        (
+            (ycc.y * 219.0 / 255.0 + 16.0).round() as u8,
+            to_video_chroma(ycc.cb).round() as u8,
+            to_video_chroma(ycc.cr).round() as u8,
+        )
+    }
+
+    #[test]
+    fn test_i420_and_nv12_agree() {
+        let green = RGB { r: 30, g: 200, b: 60 };
+        let (y, cb, cr) = video_range(green);
+
+        // 3x3 image: chroma is 2x2, the last column/row covers one pixel
+        let luma = vec![y; 9];
+        let u = vec![cb; 4];
+        let v = vec![cr; 4];
+        let uv = [cb, cr].repeat(4);
+
+        let i420 = YuvImage::new(&luma, &u, &v, 3, 3, YuvLayout::default()).unwrap();
+        let nv12_layout = YuvLayout { format: YuvFormat::Nv12, ..YuvLayout::default() };
+        let nv12 = YuvImage::new(&luma, &uv, &[], 3, 3, nv12_layout).unwrap();
+
+        for image in [i420, nv12] {
+            let chroma = image.chroma();
+            assert_eq!((chroma.width(), chroma.height()), (2, 2));
+            for (cx, cy) in [(0, 0), (1, 1)] {
+                let rgb = chroma.pixel(cx, cy);
+                assert!((rgb.r as i32 - 30).abs() <= 2, "{:?}", rgb);
+                assert!((rgb.g as i32 - 200).abs() <= 2, "{:?}", rgb);
+                assert!((rgb.b as i32 - 60).abs() <= 2, "{:?}", rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn test_contiguous_with_strides() {
+        // 2x2 I420 with padded rows: Y stride 4, chroma stride 2
+        let layout = YuvLayout {
+            y_stride: 4,
+            uv_stride: 2,
+            full_range: true,
+            ..YuvLayout::default()
+        };
+        let data = [
+            100, 100, 0, 0,
+            100, 100, 0, 0,
+            90, 0,
+            240, 0,
+        ];
+
+        let image = YuvImage::contiguous(&data, 2, 2, layout).unwrap();
+        let ycc = image.sample(0, 0);
+        assert_eq!((ycc.y, ycc.cb, ycc.cr), (100.0, 90.0, 240.0));
+
+        // The last row of the V plane doesn't need its padding
+        assert!(YuvImage::contiguous(&data[..11], 2, 2, layout).is_ok());
+        assert_eq!(
+            YuvImage::contiguous(&data[..10], 2, 2, layout).err(),
+            Some(DetectError::BufferTooShort { expected: 11, actual: 10 })
+        );
+
+        let nv12 = YuvLayout { format: YuvFormat::Nv12, y_stride: 4, uv_stride: 4, ..YuvLayout::default() };
+        assert!(YuvImage::contiguous(&data[..10], 2, 2, nv12).is_ok());
+    }
+
+    #[test]
+    fn test_plane_validation() {
+        let luma = [0u8; 16];
+        let chroma = [128u8; 4];
+
+        assert_eq!(
+            YuvImage::new(&luma, &chroma, &chroma[..3], 4, 4, YuvLayout::default()).err(),
+            Some(DetectError::BufferTooShort { expected: 4, actual: 3 })
+        );
+        let narrow = YuvLayout { y_stride: 3, ..YuvLayout::default() };
+        assert_eq!(
+            YuvImage::new(&luma, &chroma, &chroma, 4, 4, narrow).err(),
+            Some(DetectError::InvalidRowStride { row_stride: 3, min: 4 })
+        );
+        let nv12 = YuvLayout { format: YuvFormat::Nv12, ..YuvLayout::default() };
+        assert!(YuvImage::new(&luma, &[128; 8], &[], 4, 4, nv12).is_ok());
+
+        // Strides from JS can't overflow the plane size into a small one
+        for layout in [
+            YuvLayout { y_stride: usize::MAX, ..YuvLayout::default() },
+            YuvLayout { uv_stride: usize::MAX, ..YuvLayout::default() },
+        ] {
+            let invalid = Some(DetectError::InvalidDimensions { width: 4, height: 4 });
+            assert_eq!(YuvImage::new(&luma, &chroma, &chroma, 4, 4, layout).err(), invalid);
+            assert_eq!(YuvImage::contiguous(&luma, 4, 4, layout).err(), invalid);
+        }
+    }
+
+    #[test]
+    fn test_key_cb_cr_range() {
+        let green = RGB { r: 0, g: 255, b: 0 };
+        let full = YuvLayout { full_range: true, ..YuvLayout::default() };
+        let luma = [0u8; 4];
+        let chroma = [128u8; 1];
+
+        let image = YuvImage::new(&luma, &chroma, &chroma, 2, 2, full).unwrap();
+        let (cb, cr) = image.cb_cr(green);
+        let ycc = green.to_ycbcr(YCbCrMatrix::Bt709);
+        assert_eq!((cb, cr), (ycc.cb, ycc.cr));
+
+        // Video range squeezes chroma towards 128
+        let image = YuvImage::new(&luma, &chroma, &chroma, 2, 2, YuvLayout::default()).unwrap();
+        let (video_cb, video_cr) = image.cb_cr(green);
+        assert!((video_cb - 128.0).abs() < (cb - 128.0).abs());
+        assert!((video_cr - to_video_chroma(ycc.cr)).abs() < 0.001);
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn solid_rgba(width: u32, height: u32, rgb: [u8; 3]) -> Vec<u8> {
+    [rgb[0], rgb[1], rgb[2], 255].repeat((width * height) as usize)
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("chroma-detect-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    dir
+}
+
+fn chroma_detect(args: &[&str]) -> (String, Option<i32>) {
+    let output = Command::new(env!("CARGO_BIN_EXE_chroma-detect")).args(args).output().unwrap();
+    (String::from_utf8(output.stdout).unwrap(), output.status.code())
+}
+
+#[test]
+fn test_cli_batch_directory() {
+    let dir = scratch_dir("batch");
+    fs::write(dir.join("green.rgba"), solid_rgba(20, 10, [0, 255, 0])).unwrap();
+    fs::write(dir.join("nested/short.rgba"), solid_rgba(2, 2, [0, 255, 0])).unwrap();
+    fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+    let (stdout, status) = chroma_detect(&["--size", "20x10", "--format", "csv", dir.to_str().unwrap()]);
+    let rows: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(rows.len(), 3, "{}", stdout);
+    assert_eq!(rows[0], "file,r,g,b,hue,confidence,coverage,method,error");
+    assert!(rows[1].ends_with("green.rgba,0,255,0,120,1,1,hybrid,"), "{}", rows[1]);
+    assert!(rows[2].contains("short.rgba,,,,,,,,\"pixel buffer too short"), "{}", rows[2]);
+    // One file failed
+    assert_eq!(status, Some(1));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cli_decodes_netpbm() {
+    let dir = scratch_dir("netpbm");
+    let mut ppm = b"P6\n16 16\n255\n".to_vec();
+    ppm.extend_from_slice(&[0, 0, 255].repeat(256));
+    fs::write(dir.join("nested/blue.ppm"), ppm).unwrap();
+    fs::write(dir.join("broken.pgm"), "P5 16 16 0\n").unwrap();
+    fs::write(dir.join("skipped.rgba"), solid_rgba(4, 4, [0, 255, 0])).unwrap();
+
+    let (stdout, status) = chroma_detect(&[dir.to_str().unwrap()]);
+    let rows: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+    assert_eq!(rows.len(), 2, "{}", stdout);
+    assert_eq!(rows[0]["error"], "invalid image header: maxval 0 is outside 1 - 65535");
+    assert_eq!(rows[1]["hue"], 240.0);
+    assert_eq!(status, Some(1));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_cli_config_file_and_flags() {
+    let dir = scratch_dir("config");
+    let image = dir.join("blue.rgba");
+    fs::write(&image, solid_rgba(8, 8, [0, 0, 255])).unwrap();
+    let config = dir.join("config.json");
+    fs::write(&config, r#"{ "minSaturation": 0.5, "kmeansK": 2 }"#).unwrap();
+
+    let (stdout, status) = chroma_detect(&[
+        "--config", config.to_str().unwrap(),
+        "--confidence-threshold=0.5",
+        "--size", "8x8",
+        image.to_str().unwrap(),
+    ]);
+    assert_eq!(status, Some(0));
+    let row: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(row["file"], image.to_str().unwrap());
+    assert_eq!(row["hue"], 240.0);
+    assert_eq!(row["color"]["b"], 255);
+
+    // Bad flags are usage errors
+    let (_, status) = chroma_detect(&["--kmeans-k", "0", image.to_str().unwrap()]);
+    assert_eq!(status, Some(2));
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,4 +1,14 @@
-use chroma_detect::detection::{detect_chromakey, DetectionConfig};
+use std::ops::Range;
+
+use chroma_detect::codec;
+use chroma_detect::color::{ColorSpace, YCbCrMatrix, HSV, RGB};
+use chroma_detect::detection::{
+    detect_chromakey, detect_candidates, detect_chromakey_with_diagnostics,
+    detect_chromakey_with_layout, detect_chromakey_yuv, DetectError, DetectionConfig, DetectionStage, SelectionReason,
+};
+use chroma_detect::matte::{MatteConfig, generate_matte};
+use chroma_detect::pixel_format::{PixelFormat, PixelLayout};
+use chroma_detect::yuv::{YuvFormat, YuvImage, YuvLayout};
 
 fn create_solid_color_image(width: u32, height: u32, r: u8, g: u8, b: u8) -> Vec<u8> {
     let mut pixels = Vec::with_capacity((width * height * 4) as usize);
@@ -13,6 +23,36 @@ fn create_solid_color_image(width: u32, height: u32, r: u8, g: u8, b: u8) -> Vec
     pixels
 }
 
+/// `backdrop` everywhere except a `subject` box spanning columns `xs` and rows `ys`
+fn create_subject_image(width: u32, height: u32, backdrop: [u8; 4], subject: [u8; 4], xs: Range<u32>, ys: Range<u32>) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let inside = xs.contains(&x) && ys.contains(&y);
+            pixels.extend_from_slice(if inside { &subject } else { &backdrop });
+        }
+    }
+    pixels
+}
+
+/// 60x60 of 20-pixel green, red and blue blocks, three clearly separate clusters
+fn create_color_blocks_image() -> (Vec<u8>, u32, u32) {
+    let (width, height) = (60u32, 60u32);
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let rgb: [u8; 3] = match (x / 20 + y / 20) % 3 {
+                0 => [0, 200, 0],
+                1 => [200, 40, 40],
+                _ => [40, 40, 200],
+            };
+            pixels.extend_from_slice(&rgb);
+            pixels.push(255);
+        }
+    }
+    (pixels, width, height)
+}
+
 #[test]
 fn test_pure_green_screen() {
     let width = 100;
@@ -68,3 +108,452 @@ fn test_center_subject_fallback() {
     // Coverage is 40% (40 rows of 100)
     assert!(result.coverage > 0.35);
 }
+
+#[test]
+fn test_split_screen_candidates() {
+    let width = 100;
+    let height = 100;
+    // Left half green, right half blue
+    let mut pixels = create_solid_color_image(width, height, 0, 255, 0);
+    for y in 0..height {
+        for x in 50..width {
+            let idx = ((y * width + x) * 4) as usize;
+            pixels[idx + 1] = 0;
+            pixels[idx + 2] = 255;
+        }
+    }
+
+    let config = DetectionConfig::default();
+    let candidates = detect_candidates(&pixels, width, height, &config).unwrap();
+
+    assert_eq!(candidates.len(), 2, "Expected one green and one blue candidate");
+    let hues: Vec<f32> = candidates.iter().map(|c| c.result.hue).collect();
+    assert!(hues.iter().any(|h| (h - 120.0).abs() < 5.0), "Missing green, got {:?}", hues);
+    assert!(hues.iter().any(|h| (h - 240.0).abs() < 5.0), "Missing blue, got {:?}", hues);
+
+    // Ranked best first
+    assert!(candidates[0].score >= candidates[1].score);
+}
+
+#[test]
+fn test_candidates_merge_agreeing_stages() {
+    let pixels = create_solid_color_image(100, 100, 0, 255, 0);
+
+    let config = DetectionConfig::default();
+    let candidates = detect_candidates(&pixels, 100, 100, &config).unwrap();
+
+    // Full-frame, edge and cluster stages all find the same green
+    assert_eq!(candidates.len(), 1);
+    assert!((candidates[0].result.hue - 120.0).abs() < 5.0);
+    assert!(candidates[0].score >= candidates[0].result.confidence);
+}
+
+#[test]
+fn test_buffer_validation_errors() {
+    let config = DetectionConfig::default();
+
+    let pixels = create_solid_color_image(10, 10, 0, 255, 0);
+    assert_eq!(
+        detect_chromakey(&pixels, 20, 10, &config).unwrap_err(),
+        DetectError::BufferTooShort { expected: 800, actual: 400 }
+    );
+    assert_eq!(
+        detect_chromakey(&pixels, 0, 10, &config).unwrap_err(),
+        DetectError::InvalidDimensions { width: 0, height: 10 }
+    );
+}
+
+#[test]
+fn test_no_dominant_color_error() {
+    // 36 pale hues in a diagonal pattern, each under 3% of any region
+    // and too desaturated for clustering
+    let width = 100;
+    let height = 100;
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let hue = ((x + y) % 36 * 10) as f32;
+            let rgb = HSV { h: hue, s: 0.2, v: 0.9 }.to_rgb();
+            pixels.extend_from_slice(&[rgb.r, rgb.g, rgb.b, 255]);
+        }
+    }
+
+    let config = DetectionConfig::default();
+    match detect_chromakey(&pixels, width, height, &config) {
+        Err(DetectError::NoDominantColor { best_coverage, min_area_percentage }) => {
+            assert!(best_coverage < min_area_percentage);
+        }
+        other => panic!("Expected NoDominantColor, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_diagnostics_full_frame_confident() {
+    let pixels = create_solid_color_image(100, 100, 0, 255, 0);
+
+    let config = DetectionConfig::default();
+    let report = detect_chromakey_with_diagnostics(&pixels, 100, 100, &config).unwrap();
+    let expected = detect_chromakey(&pixels, 100, 100, &config).unwrap();
+
+    assert_eq!(report.reason, SelectionReason::FullFrameConfident);
+    assert_eq!(report.selected_stage, Some(DetectionStage::FullFrame));
+    assert_eq!(report.selected.unwrap().hue, expected.hue);
+    assert!(report.error.is_none());
+
+    // Every stage still ran and reported its samples
+    assert_eq!(report.full_frame.samples, 10_000);
+    assert_eq!(report.full_frame.stride, 1);
+    assert!(report.edges.samples > 0);
+    assert!(!report.edges.used);
+    assert_eq!(report.clusters.samples, 10_000);
+    assert!(!report.kmeans_clusters.is_empty());
+}
+
+#[test]
+fn test_diagnostics_matches_detection_fallback() {
+    // Green band covering 40% of the frame: below the confidence threshold
+    let width = 100;
+    let height = 100;
+    let mut pixels = create_solid_color_image(width, height, 0, 0, 0);
+    for y in 30..70 {
+        for x in 0..width {
+            let idx = ((y * width + x) * 4) as usize;
+            pixels[idx + 1] = 255;
+        }
+    }
+
+    let config = DetectionConfig::default();
+    let report = detect_chromakey_with_diagnostics(&pixels, width, height, &config).unwrap();
+    let expected = detect_chromakey(&pixels, width, height, &config).unwrap();
+
+    assert_eq!(report.reason, SelectionReason::HighestConfidence);
+    assert!(report.edges.used && report.clusters.used);
+    let selected = report.selected.unwrap();
+    assert_eq!(selected.method_used, expected.method_used);
+    assert_eq!(selected.confidence, expected.confidence);
+}
+
+#[test]
+fn test_min_saturation_gates_candidates() {
+    // Pale green backdrop, saturation ~0.2
+    let pixels = create_solid_color_image(100, 100, 184, 230, 184);
+
+    let lenient = DetectionConfig { min_saturation: 0.1, ..DetectionConfig::default() };
+    let result = detect_chromakey(&pixels, 100, 100, &lenient).expect("Should accept pale green");
+    assert!((result.hue - 120.0).abs() < 5.0);
+
+    let strict = DetectionConfig::default();
+    match detect_chromakey(&pixels, 100, 100, &strict) {
+        Err(DetectError::Desaturated { saturation, min_saturation }) => {
+            assert!(saturation < min_saturation);
+        }
+        other => panic!("Expected Desaturated, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_config_validation() {
+    assert!(DetectionConfig::default().validate().is_ok());
+
+    let overlapping_edges = DetectionConfig { edge_sample_percentage: 0.6, ..DetectionConfig::default() };
+    assert_eq!(
+        overlapping_edges.validate().unwrap_err(),
+        DetectError::ConfigOutOfRange { field: "edge_sample_percentage".into(), value: 0.6, min: 0.0, max: 0.5 }
+    );
+
+    // Errors survive a JSON round trip (saved video sessions keep them)
+    let err = overlapping_edges.validate().unwrap_err();
+    let json = serde_json::to_string(&err).unwrap();
+    assert_eq!(serde_json::from_str::<DetectError>(&json).unwrap(), err);
+    let json = r#"{"kind":"config_out_of_range","field":"added_later","value":2.0,"min":0.0,"max":1.0}"#;
+    assert!(matches!(
+        serde_json::from_str(json),
+        Ok(DetectError::ConfigOutOfRange { field, .. }) if field == "added_later"
+    ));
+
+    // JS passes camelCase names
+    let from_js: DetectionConfig = serde_json::from_str(r#"{ "grayscaleSaturation": 0.2 }"#).unwrap();
+    assert_eq!(from_js.grayscale_saturation, 0.2);
+
+    let nan_threshold = DetectionConfig { confidence_threshold: f32::NAN, ..DetectionConfig::default() };
+    assert!(nan_threshold.validate().is_err());
+
+    let no_clusters = DetectionConfig { kmeans_k: 0, ..DetectionConfig::default() };
+    assert!(matches!(
+        no_clusters.validate(),
+        Err(DetectError::ConfigOutOfRange { field, .. }) if field == "kmeans_k"
+    ));
+
+    // Batches past the mini-batch threshold would never be smaller than full k-means
+    let huge_batch = DetectionConfig { kmeans_batch_size: usize::MAX, ..DetectionConfig::default() };
+    assert!(matches!(
+        huge_batch.validate(),
+        Err(DetectError::ConfigOutOfRange { field, .. }) if field == "kmeans_batch_size"
+    ));
+    assert!(DetectionConfig { kmeans_batch_size: 1024, ..DetectionConfig::default() }.validate().is_ok());
+
+    // Detection refuses to run with an invalid config
+    let pixels = create_solid_color_image(10, 10, 0, 255, 0);
+    let bad_area = DetectionConfig { min_area_percentage: 1.5, ..DetectionConfig::default() };
+    assert!(matches!(
+        detect_chromakey(&pixels, 10, 10, &bad_area),
+        Err(DetectError::ConfigOutOfRange { field, .. }) if field == "min_area_percentage"
+    ));
+}
+
+#[test]
+fn test_perceptual_color_spaces() {
+    let pixels = create_solid_color_image(100, 100, 0, 0, 255);
+
+    let from_js: DetectionConfig = serde_json::from_str(r#"{ "colorSpace": "oklab" }"#).unwrap();
+    assert_eq!(from_js.color_space, ColorSpace::Oklab);
+
+    for color_space in [ColorSpace::Lab, ColorSpace::Oklab] {
+        let config = DetectionConfig { color_space, ..DetectionConfig::default() };
+        let result = detect_chromakey(&pixels, 100, 100, &config).expect("Should detect blue");
+        assert!((result.hue - 240.0).abs() < 5.0, "{:?}: hue {}", color_space, result.hue);
+    }
+}
+
+#[test]
+fn test_padded_bgra_frame() {
+    // BGRA green screen with 16 bytes of row padding, as native decoders produce
+    let (width, height) = (100u32, 80u32);
+    let row_stride = width as usize * 4 + 16;
+    let mut pixels = vec![0u8; row_stride * height as usize];
+    for row in pixels.chunks_exact_mut(row_stride) {
+        for pixel in row[..width as usize * 4].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[20, 200, 40, 255]);
+        }
+    }
+
+    let layout = PixelLayout::new(PixelFormat::Bgra, row_stride);
+    let result = detect_chromakey_with_layout(&pixels, width, height, &layout, &DetectionConfig::default())
+        .expect("Should detect green");
+    let expected = create_solid_color_image(width, height, 40, 200, 20);
+    let rgba = detect_chromakey(&expected, width, height, &DetectionConfig::default()).unwrap();
+
+    assert_eq!((result.color.r, result.color.g, result.color.b), (rgba.color.r, rgba.color.g, rgba.color.b));
+    assert!((result.hue - rgba.hue).abs() < 0.01);
+
+    // Tightly packed 3-byte pixels
+    let rgb24 = [40u8, 200, 20].repeat(width as usize * height as usize);
+    let layout = PixelLayout::new(PixelFormat::Rgb24, 0);
+    let result = detect_chromakey_with_layout(&rgb24, width, height, &layout, &DetectionConfig::default()).unwrap();
+    assert_eq!((result.color.r, result.color.g, result.color.b), (rgba.color.r, rgba.color.g, rgba.color.b));
+}
+
+#[test]
+fn test_yuv_planes() {
+    // Full-range BT.601 green screen with a gray subject in the middle
+    let (width, height) = (64u32, 48u32);
+    let layout = YuvLayout { matrix: YCbCrMatrix::Bt601, full_range: true, ..YuvLayout::default() };
+    let green = RGB { r: 20, g: 190, b: 50 }.to_ycbcr(YCbCrMatrix::Bt601);
+    let is_subject = |x: u32, y: u32| (16..48).contains(&x) && (12..36).contains(&y);
+
+    let mut luma = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            luma.push(if is_subject(x, y) { 128 } else { green.y.round() as u8 });
+        }
+    }
+    let (mut u, mut v) = (Vec::new(), Vec::new());
+    for cy in 0..height / 2 {
+        for cx in 0..width / 2 {
+            let subject = is_subject(cx * 2, cy * 2);
+            u.push(if subject { 128 } else { green.cb.round() as u8 });
+            v.push(if subject { 128 } else { green.cr.round() as u8 });
+        }
+    }
+
+    let image = YuvImage::new(&luma, &u, &v, width, height, layout).unwrap();
+    let result = detect_chromakey_yuv(&image, &DetectionConfig::default()).expect("Should detect green");
+
+    let expected_hue = RGB { r: 20, g: 190, b: 50 }.to_hsv().h;
+    assert!((result.result.hue - expected_hue).abs() < 3.0, "hue {}", result.result.hue);
+    assert!((result.cb - green.cb).abs() < 2.0 && (result.cr - green.cr).abs() < 2.0);
+
+    // Same frame as NV12
+    let uv: Vec<u8> = u.iter().zip(&v).flat_map(|(&cb, &cr)| [cb, cr]).collect();
+    let nv12 = YuvLayout { format: YuvFormat::Nv12, ..layout };
+    let image = YuvImage::new(&luma, &uv, &[], width, height, nv12).unwrap();
+    let nv12_result = detect_chromakey_yuv(&image, &DetectionConfig::default()).unwrap();
+    assert_eq!(nv12_result.result.hue, result.result.hue);
+}
+
+#[test]
+fn test_alpha_respected() {
+    // Overlay: transparent region whose color bytes are left at magenta,
+    // opaque green backdrop in the bottom 40% of rows
+    let (width, height) = (100u32, 100u32);
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for _ in 0..width {
+            if y < 60 {
+                pixels.extend_from_slice(&[255, 0, 255, 0]);
+            } else {
+                pixels.extend_from_slice(&[0, 200, 0, 255]);
+            }
+        }
+    }
+
+    let ignored = detect_chromakey(&pixels, width, height, &DetectionConfig::default()).unwrap();
+    assert!((ignored.hue - 300.0).abs() < 5.0, "Alpha ignored by default, got {}", ignored.hue);
+
+    let config = DetectionConfig { use_alpha: true, ..DetectionConfig::default() };
+    let result = detect_chromakey(&pixels, width, height, &config).expect("Should detect green");
+    assert!((result.hue - 120.0).abs() < 5.0, "Hue should be near 120, got {}", result.hue);
+    assert!(result.coverage > 0.9);
+
+    let transparent = vec![0u8; 40 * 40 * 4];
+    assert_eq!(detect_chromakey(&transparent, 40, 40, &config).err(), Some(DetectError::Transparent));
+}
+
+#[test]
+fn test_red_key_across_zero_hue() {
+    // Red backdrop lit unevenly: hues just below 360° and just above 0°
+    let (width, height) = (100u32, 100u32);
+    let mut pixels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if (x + y) % 2 == 0 {
+                pixels.extend_from_slice(&[230, 0, 8, 255]);
+            } else {
+                pixels.extend_from_slice(&[230, 6, 0, 255]);
+            }
+        }
+    }
+
+    let result = detect_chromakey(&pixels, width, height, &DetectionConfig::default()).expect("Should detect red");
+    let hue_diff = result.hue.min(360.0 - result.hue);
+    assert!(hue_diff < 5.0, "Hue should be near 0, got {}", result.hue);
+
+    // Every stage's red is one candidate, not one per side of 0°
+    let candidates = detect_candidates(&pixels, width, height, &DetectionConfig::default()).unwrap();
+    let reds = candidates.iter().filter(|c| c.result.hue.min(360.0 - c.result.hue) < 10.0).count();
+    assert_eq!(reds, 1, "{:?}", candidates);
+}
+
+#[test]
+fn test_kmeans_config() {
+    let (pixels, width, height) = create_color_blocks_image();
+
+    let config = DetectionConfig { kmeans_k: 5, kmeans_seed: 1234, ..DetectionConfig::default() };
+    let report = detect_chromakey_with_diagnostics(&pixels, width, height, &config).unwrap();
+    assert_eq!(report.kmeans_clusters.len(), 5);
+
+    // A fixed seed gives the same clusters every run
+    let again = detect_chromakey_with_diagnostics(&pixels, width, height, &config).unwrap();
+    let sizes = |r: &chroma_detect::detection::DetectionDiagnostics| {
+        r.kmeans_clusters.iter().map(|c| c.size).collect::<Vec<_>>()
+    };
+    assert_eq!(sizes(&report), sizes(&again));
+}
+
+#[test]
+fn test_auto_k_diagnostics() {
+    // Three-color blocks: auto-k should settle on three clusters
+    let (pixels, width, height) = create_color_blocks_image();
+
+    let config = DetectionConfig { kmeans_auto_k: true, ..DetectionConfig::default() };
+    let report = detect_chromakey_with_diagnostics(&pixels, width, height, &config).unwrap();
+    assert_eq!(report.kmeans_k, 3);
+    assert_eq!(report.kmeans_clusters.len(), 3);
+    assert_eq!(report.kmeans_k_scores.len(), 5);
+
+    let fixed = detect_chromakey_with_diagnostics(&pixels, width, height, &DetectionConfig::default()).unwrap();
+    assert!(fixed.kmeans_k_scores.is_empty());
+
+    let inverted = DetectionConfig { kmeans_auto_k: true, kmeans_min_k: 5, kmeans_max_k: 3, ..DetectionConfig::default() };
+    assert!(matches!(
+        inverted.validate(),
+        Err(DetectError::ConfigOutOfRange { field, .. }) if field == "kmeans_max_k"
+    ));
+}
+
+#[test]
+fn test_detect_from_decoded_netpbm() {
+    // 16-bit PAM with an opaque green border around a transparent center
+    let (width, height) = (20u32, 20u32);
+    let mut pam = format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 65535\nTUPLTYPE RGB_ALPHA\nENDHDR\n", width, height)
+        .into_bytes();
+    let rgba = create_subject_image(width, height, [0, 255, 0, 255], [255, 0, 0, 0], 4..width - 4, 4..height - 4);
+    for &sample in &rgba {
+        pam.extend_from_slice(&(sample as u16 * 257).to_be_bytes());
+    }
+
+    let image = codec::decode(&pam).unwrap();
+    assert_eq!(image.pixels.len(), (width * height * 4) as usize);
+
+    let config = DetectionConfig { use_alpha: true, ..DetectionConfig::default() };
+    let result = detect_chromakey(&image.pixels, image.width, image.height, &config).unwrap();
+    assert_eq!((result.color.r, result.color.g, result.color.b), (0, 255, 0));
+}
+
+#[test]
+fn test_detect_from_decoded_bmp_and_tga() {
+    // Blue border around a red center, as a bottom-up 24-bit BMP and an RLE TGA
+    let (width, height) = (16u16, 12u16);
+    let (w, h) = (width as u32, height as u32);
+    let rgba = create_subject_image(w, h, [0, 0, 255, 255], [255, 0, 0, 255], 3..w - 3, 3..h - 3);
+    let bgr = |x: u16, y: u16| {
+        let i = (y as usize * width as usize + x as usize) * 4;
+        [rgba[i + 2], rgba[i + 1], rgba[i]]
+    };
+
+    let stride = (width as usize * 3).div_ceil(4) * 4;
+    let mut bmp = b"BM".to_vec();
+    for value in [54 + stride as u32 * height as u32, 0, 54, 40, width as u32, height as u32] {
+        bmp.extend_from_slice(&value.to_le_bytes());
+    }
+    bmp.extend_from_slice(&[1, 0, 24, 0]);
+    bmp.extend_from_slice(&[0; 24]);
+    for y in (0..height).rev() {
+        let mut row: Vec<u8> = (0..width).flat_map(|x| bgr(x, y)).collect();
+        row.resize(stride, 0);
+        bmp.extend_from_slice(&row);
+    }
+
+    // One repeat packet per pixel, top row first
+    let mut tga = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    tga.extend_from_slice(&width.to_le_bytes());
+    tga.extend_from_slice(&height.to_le_bytes());
+    tga.extend_from_slice(&[24, 0x20]);
+    for y in 0..height {
+        for x in 0..width {
+            tga.push(0x80);
+            tga.extend_from_slice(&bgr(x, y));
+        }
+    }
+
+    let from_bmp = codec::decode(&bmp).unwrap();
+    let from_tga = codec::decode(&tga).unwrap();
+    assert_eq!(from_bmp, from_tga);
+
+    let result = detect_chromakey(&from_bmp.pixels, from_bmp.width, from_bmp.height, &DetectionConfig::default()).unwrap();
+    assert_eq!((result.color.r, result.color.g, result.color.b), (0, 0, 255));
+}
+
+#[test]
+fn test_qoi_fixture_and_matte_preview() {
+    // Green screen with a skin-toned subject, stored as a QOI fixture
+    let (width, height) = (32u32, 24u32);
+    let frame = create_subject_image(width, height, [20, 200, 40, 255], [220, 170, 140, 255], 10..22, 8..20);
+    let fixture = codec::qoi::encode(&frame, width, height).unwrap();
+    assert!(fixture.len() < frame.len() / 4);
+
+    let decoded = codec::decode(&fixture).unwrap();
+    assert_eq!(decoded.pixels, frame);
+    let key = detect_chromakey(&decoded.pixels, width, height, &DetectionConfig::default()).unwrap();
+    let original = detect_chromakey(&frame, width, height, &DetectionConfig::default()).unwrap();
+    assert_eq!((key.color.r, key.color.g, key.color.b), (original.color.r, original.color.g, original.color.b));
+    assert_eq!(key.confidence, original.confidence);
+
+    // The matte, written as a grayscale debug preview
+    let matte = generate_matte(&decoded.pixels, width, height, &key, &MatteConfig::default()).unwrap();
+    let preview: Vec<u8> = matte.iter().flat_map(|&alpha| [alpha, alpha, alpha, 255]).collect();
+    let preview = codec::decode(&codec::qoi::encode(&preview, width, height).unwrap()).unwrap();
+    assert_eq!(&preview.pixels[..4], [0, 0, 0, 255]);
+    let center = ((12 * width + 16) * 4) as usize;
+    assert_eq!(&preview.pixels[center..center + 4], [255, 255, 255, 255]);
+}